@@ -0,0 +1,106 @@
+use std::path::PathBuf;
+
+use clap::{Args, Parser, Subcommand};
+use taktische_zeichen::{Command, Paths};
+
+#[derive(Debug, Parser)]
+#[command(name = "taktische-zeichen", version, about = "Generates tactical symbols from templates")]
+pub(crate) struct Cli {
+    #[command(flatten)]
+    paths: PathArgs,
+
+    #[command(subcommand)]
+    command: Option<CommandArgs>,
+}
+
+impl Cli {
+    /// The command to run, `build` if none is given, and the paths it works on.
+    pub(crate) fn into_parts(self) -> (Command, Paths) {
+        let command = match self.command.unwrap_or(CommandArgs::Build { no_png: false }) {
+            CommandArgs::Build { no_png } => Command::Build { no_png },
+            CommandArgs::Render => Command::Render,
+            CommandArgs::Png => Command::Png,
+            CommandArgs::Drawio => Command::Drawio,
+            CommandArgs::List => Command::List,
+            CommandArgs::Validate => Command::Validate,
+            CommandArgs::Lint => Command::Lint,
+            CommandArgs::Clean => Command::Clean,
+            CommandArgs::Watch { no_png } => Command::Watch { no_png },
+            CommandArgs::Serve { address } => Command::Serve { address },
+            CommandArgs::Prune { dry_run } => Command::Prune { dry_run },
+        };
+        let paths = Paths {
+            config: self.paths.config,
+            volunteer: self.paths.volunteer,
+            templates: self.paths.templates,
+            static_dir: self.paths.static_dir,
+            output: self.paths.output,
+        };
+        (command, paths)
+    }
+}
+
+#[derive(Debug, Args)]
+struct PathArgs {
+    /// Symbol catalogue
+    #[arg(long, global = true, default_value = "config.toml")]
+    config: PathBuf,
+
+    /// Person / volunteer catalogue [default: volunteer.toml, skipped if missing]
+    #[arg(long, global = true)]
+    volunteer: Option<PathBuf>,
+
+    /// Root directory of the `*.template.svg` files
+    #[arg(long, global = true, default_value = "icons")]
+    templates: PathBuf,
+
+    /// Root directory of the static svg files
+    #[arg(long = "static", global = true, default_value = "static")]
+    static_dir: PathBuf,
+
+    /// Output directory
+    #[arg(short, long, global = true, default_value = "build")]
+    output: PathBuf,
+}
+
+#[derive(Debug, Subcommand)]
+enum CommandArgs {
+    /// Runs every stage: render, png and drawio (default)
+    Build {
+        /// Skip the png conversion even if `enable_png` is set
+        #[arg(long)]
+        no_png: bool,
+    },
+    /// Renders the svg files of the catalogue and copies the static files
+    Render,
+    /// Converts every svg file in the output directory to png
+    Png,
+    /// Creates the draw.io libraries from the svg files in the output directory
+    Drawio,
+    /// Lists every file the catalogue would produce
+    List,
+    /// Checks the catalogue against the template tree without rendering
+    Validate,
+    /// Renders every template with sample values and checks the resulting svg
+    Lint,
+    /// Removes everything a build wrote into the output directory
+    Clean,
+    /// Builds, then rebuilds the affected outputs whenever a template, static file or catalogue changes
+    Watch {
+        /// Skip the png conversion even if `enable_png` is set
+        #[arg(long)]
+        no_png: bool,
+    },
+    /// Serves a gallery of the output directory
+    Serve {
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:8000")]
+        address: String,
+    },
+    /// Removes outputs the catalogue and the static files don't produce anymore
+    Prune {
+        /// Only list the files which would be removed
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
@@ -1,41 +1,632 @@
-use std::fs;
+use std::{
+    collections::BTreeMap,
+    fmt,
+    fs,
+    path::{Path, PathBuf},
+};
 
-use serde::Deserialize;
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize};
+use walkdir::WalkDir;
 
-pub fn parse() -> (Config, VolunteerConfig) {
-    let config_text = fs::read_to_string("config.toml").expect("Couldn't parse config file.");
-    let volunteer_text = fs::read_to_string("volunteer.toml").expect("Couldn't parse volunteer file.");
+use crate::error::Error;
 
-    let config: Config = toml::from_str(&*config_text).unwrap();
-    let volunteer_config: VolunteerConfig = toml::from_str(&*volunteer_text).unwrap();
+/// Volunteer file read if no other one is given, it may be missing.
+const DEFAULT_VOLUNTEER: &str = "volunteer.toml";
 
-    return (config, volunteer_config);
+/// The volunteer file used for an optional `--volunteer`.
+pub(crate) fn volunteer_path(volunteer: Option<&Path>) -> &Path {
+    volunteer.unwrap_or(Path::new(DEFAULT_VOLUNTEER))
+}
+
+/// Reads the catalogue with all of its includes and the volunteer file.
+///
+/// Without an explicit `volunteer_path` a missing `volunteer.toml` is skipped, a given file has to exist.
+pub fn parse(
+    config_path: &Path,
+    volunteer_path: Option<&Path>,
+) -> Result<(Config, VolunteerConfig), Error> {
+    let config = parse_config(config_path)?;
+    let path = self::volunteer_path(volunteer_path);
+    let volunteer_config: VolunteerConfig = if volunteer_path.is_some() || path.exists() {
+        let (mut volunteer_config, text): (VolunteerConfig, String) = read_toml(path)?;
+        let lines = header_lines(&text, "personen");
+        for (index, person) in volunteer_config.personen.iter_mut().flatten().enumerate() {
+            person.source = Source::new(path, lines.get(index).copied());
+        }
+        volunteer_config
+    } else {
+        VolunteerConfig::default()
+    };
+
+    Ok((config, volunteer_config))
+}
+
+/// The catalogue without the volunteers.
+pub(crate) fn parse_config(config_path: &Path) -> Result<Config, Error> {
+    let mut visited = Vec::new();
+    let mut config = read_config(config_path, &mut visited)?;
+    config.files = visited;
+    Ok(config)
+}
+
+fn read_config(
+    path: &Path,
+    visited: &mut Vec<PathBuf>,
+) -> Result<Config, Error> {
+    let included = !visited.is_empty();
+    visited.push(fs::canonicalize(path).map_err(|e| Error::io(path, e))?);
+    let (mut config, text): (Config, String) = read_toml(path)?;
+    if included {
+        check_build_settings(path, &text)?;
+    }
+    for (key, entries) in config.symbols.iter_mut() {
+        let lines = header_lines(&text, key);
+        for (index, entry) in entries.iter_mut().enumerate() {
+            entry.source = Source::new(path, lines.get(index).copied());
+        }
+    }
+
+    let base = path.parent().unwrap_or(Path::new(""));
+    for include in config.include.clone() {
+        let include = base.join(include);
+        // an included directory is recorded as well, files added to it later belong to the catalogue
+        if include.is_dir() {
+            let canonical = fs::canonicalize(&include).map_err(|e| Error::io(&include, e))?;
+            if !visited.contains(&canonical) {
+                visited.push(canonical);
+            }
+        }
+        for file in include_files(&include)? {
+            let canonical = fs::canonicalize(&file).map_err(|e| Error::io(&file, e))?;
+            if !visited.contains(&canonical) {
+                let included = read_config(&file, visited)?;
+                config.merge(included);
+            }
+        }
+    }
+
+    Ok(config)
+}
+
+/// Settings of the whole build, which only the file given with `--config` may set.
+const BUILD_SETTINGS: [&str; 4] = ["enable_png", "collisions", "status_variants", "raster"];
+
+/// Fails if an included file sets a build setting, merging would silently drop it.
+fn check_build_settings(
+    path: &Path,
+    text: &str,
+) -> Result<(), Error> {
+    let table: toml::Table = toml::from_str(text).map_err(|source| Error::Config { path: path.to_path_buf(), source: Box::new(source) })?;
+    let set: Vec<&str> = BUILD_SETTINGS
+        .into_iter()
+        .filter(|key| table.contains_key(*key))
+        .collect();
+    if set.is_empty() {
+        return Ok(());
+    }
+    Err(Error::Config {
+        path: path.to_path_buf(),
+        source: format!("{} can only be set in the main catalogue file, not in an included one", set.join(", ")).into(),
+    })
+}
+
+/// A file itself or every `*.toml` file below a directory, sorted by path.
+fn include_files(path: &Path) -> Result<Vec<PathBuf>, Error> {
+    if !path.is_dir() {
+        fs::metadata(path).map_err(|e| Error::io(path, e))?;
+        return Ok(vec![path.to_path_buf()]);
+    }
+    Ok(WalkDir::new(path)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|entry| {
+            entry.file_type().is_file()
+                && entry.path()
+                .extension()
+                .is_some_and(|extension| extension == "toml")
+        })
+        .map(|entry| entry.path().to_path_buf())
+        .collect())
+}
+
+fn read_toml<T: DeserializeOwned>(path: &Path) -> Result<(T, String), Error> {
+    let text = fs::read_to_string(path).map_err(|e| Error::io(path, e))?;
+    let value = toml::from_str(&text).map_err(|source| Error::Config { path: path.to_path_buf(), source: Box::new(source) })?;
+    Ok((value, text))
+}
+
+/// Line numbers of the `[[key]]` headers, in order of appearance.
+fn header_lines(
+    text: &str,
+    key: &str,
+) -> Vec<usize> {
+    text.lines()
+        .enumerate()
+        .filter_map(|(index, line)| {
+            let header = line
+                .split('#')
+                .next()
+                .unwrap_or("")
+                .trim()
+                .strip_prefix("[[")?
+                .strip_suffix("]]")?
+                .trim()
+                .trim_matches('"');
+            (header == key).then_some(index + 1)
+        })
+        .collect()
+}
+
+/// Where an entry is declared, for error messages.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Source {
+    pub(crate) file: PathBuf,
+    pub(crate) line: Option<usize>,
+}
+
+impl Source {
+    fn new(file: &Path, line: Option<usize>) -> Source {
+        Source {
+            file: file.to_path_buf(),
+            line,
+        }
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}", self.file.display(), line),
+            None => write!(f, "{}", self.file.display()),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize,Clone)]
 pub(crate) struct Config {
+    #[serde(default)]
     pub(crate) enable_png: bool,
-    pub(crate) thw: Vec<DescriptionObjects>,
-    pub(crate) fw: Vec<DescriptionObjects>,
-    pub(crate) zoll: Vec<DescriptionObjects>,
-    pub(crate) rettung: Vec<DescriptionObjects>,
-    pub(crate) pol: Vec<DescriptionObjects>,
-    pub(crate) bw: Vec<DescriptionObjects>,
-    pub(crate) kats: Vec<DescriptionObjects>,
-    pub(crate) alle: Vec<DescriptionObjects>,
+    /// Further catalogue files or directories, relative to the including file
+    #[serde(default)]
+    pub(crate) include: Vec<PathBuf>,
+    /// What the build does if two entries write the same file
+    #[serde(default)]
+    pub(crate) collisions: CollisionMode,
+    /// Also generates every symbol with these statuses, each into `status/<status>/`
+    #[serde(default)]
+    pub(crate) status_variants: Vec<Status>,
+    #[serde(default)]
+    pub(crate) raster: Raster,
+    #[serde(default)]
+    pub(crate) organisations: Vec<Organisation>,
+    /// Every other top level key is the symbol list of the organisation with that `key`.
+    #[serde(flatten)]
+    pub(crate) symbols: BTreeMap<String, Vec<DescriptionObjects>>,
+    /// The catalogue file and every file or directory it includes, canonicalized
+    #[serde(skip)]
+    pub(crate) files: Vec<PathBuf>,
+}
+
+impl Config {
+    /// Adds the organisations and symbols of an included file, a redeclared organisation replaces the earlier one.
+    /// The build settings of an included file are rejected by `check_build_settings` before.
+    fn merge(&mut self, other: Config) {
+        for organisation in other.organisations {
+            match self.organisations
+                .iter_mut()
+                .find(|existing| existing.name == organisation.name) {
+                Some(existing) => *existing = organisation,
+                None => self.organisations.push(organisation),
+            }
+        }
+        for (key, entries) in other.symbols {
+            self.symbols
+                .entry(key)
+                .or_default()
+                .extend(entries);
+        }
+    }
+
+    /// Looks up an organisation by key or name, falling back to the defaults for unknown ones.
+    pub(crate) fn organisation(&self, name: &str) -> Organisation {
+        self.find_organisation(name)
+            .cloned()
+            .unwrap_or_else(|| Organisation::unknown(name))
+    }
+
+    pub(crate) fn find_organisation(&self, name: &str) -> Option<&Organisation> {
+        self.organisations
+            .iter()
+            .find(|organisation| {
+                organisation.key().eq_ignore_ascii_case(name)
+                    || organisation.name.eq_ignore_ascii_case(name)
+            })
+    }
+
+    /// Symbol lists in declaration order of the organisations, followed by undeclared ones.
+    pub(crate) fn catalogue(&self) -> Vec<(Organisation, Vec<DescriptionObjects>)> {
+        let mut catalogue: Vec<(Organisation, Vec<DescriptionObjects>)> = self.organisations
+            .iter()
+            .filter_map(|organisation| {
+                self.symbols
+                    .get(&organisation.key())
+                    .map(|entries| (organisation.clone(), entries.clone()))
+            })
+            .collect();
+        self.symbols
+            .iter()
+            .filter(|(key, _)| self.find_organisation(key).is_none())
+            .for_each(|(key, entries)| catalogue.push((Organisation::unknown(key), entries.clone())));
+        catalogue
+    }
+}
+
+/// Sizes and formats of the raster images, with overrides per organisation or group.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub(crate) struct Raster {
+    #[serde(flatten)]
+    pub(crate) settings: RasterSettings,
+    /// Keyed by the path below `svg/` starting with the organisation name, e.g. `THW` or `THW/FK`,
+    /// for catalogue and volunteer outputs alike; the longest match wins
+    #[serde(default)]
+    pub(crate) overrides: BTreeMap<String, RasterSettings>,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub(crate) struct RasterSettings {
+    pub(crate) sizes: Option<Vec<u32>>,
+    pub(crate) formats: Option<Vec<RasterFormat>>,
+    /// Background of formats without transparency
+    pub(crate) background: Option<String>,
+    pub(crate) jpeg_quality: Option<u8>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum RasterFormat {
+    Png,
+    Webp,
+    Qoi,
+    Jpeg,
+}
+
+impl RasterFormat {
+    pub(crate) const ALL: [RasterFormat; 4] = [
+        RasterFormat::Png,
+        RasterFormat::Webp,
+        RasterFormat::Qoi,
+        RasterFormat::Jpeg,
+    ];
+
+    /// Directory name next to `svg/`
+    pub(crate) fn dir(&self) -> &'static str {
+        match self {
+            RasterFormat::Png => "png",
+            RasterFormat::Webp => "webp",
+            RasterFormat::Qoi => "qoi",
+            RasterFormat::Jpeg => "jpeg",
+        }
+    }
+
+    pub(crate) fn extension(&self) -> &'static str {
+        match self {
+            RasterFormat::Jpeg => "jpg",
+            _ => self.dir(),
+        }
+    }
+}
+
+impl Raster {
+    /// The settings for a symbol, `key` is its path below `svg/`.
+    pub(crate) fn resolve(&self, key: &str) -> ResolvedRaster {
+        let mut resolved = self.settings.clone();
+
+        let mut overrides: Vec<(&String, &RasterSettings)> = self.overrides
+            .iter()
+            .filter(|(prefix, _)| {
+                let prefix = prefix.trim_matches('/');
+                key == prefix || key.starts_with(&format!("{}/", prefix))
+            })
+            .collect();
+        overrides.sort_by_key(|(prefix, _)| prefix.len());
+        overrides
+            .into_iter()
+            .for_each(|(_, settings)| resolved.apply(settings));
+
+        ResolvedRaster {
+            sizes: resolved.sizes.unwrap_or_else(|| vec![128, 256, 512, 1024, 2048]),
+            formats: resolved.formats.unwrap_or_else(|| vec![RasterFormat::Png]),
+            background: resolved.background.unwrap_or_else(|| "#fff".to_string()),
+            jpeg_quality: resolved.jpeg_quality.unwrap_or(90),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub(crate) struct ResolvedRaster {
+    pub(crate) sizes: Vec<u32>,
+    pub(crate) formats: Vec<RasterFormat>,
+    pub(crate) background: String,
+    pub(crate) jpeg_quality: u8,
+}
+
+impl RasterSettings {
+    fn apply(&mut self, other: &RasterSettings) {
+        if other.sizes.is_some() {
+            self.sizes.clone_from(&other.sizes);
+        }
+        if other.formats.is_some() {
+            self.formats.clone_from(&other.formats);
+        }
+        if other.background.is_some() {
+            self.background.clone_from(&other.background);
+        }
+        if other.jpeg_quality.is_some() {
+            self.jpeg_quality = other.jpeg_quality;
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum CollisionMode {
+    /// Prints a warning, the first declared entry wins
+    #[default]
+    Warn,
+    /// Aborts before rendering
+    Error,
+}
+
+/// Frames drawn by `compose/rahmen.template.xml`.
+pub(crate) const RAHMEN: [&str; 4] = ["einheit", "fahrzeug", "stelle", "gebaeude"];
+/// Unit sizes drawn by `groesse.template.xml`, from the smallest to the largest.
+pub(crate) const GROESSEN: [&str; 8] = [
+    "trupp",
+    "staffel",
+    "gruppe",
+    "zug",
+    "bereitschaft",
+    "abteilung",
+    "verband",
+    "grossverband",
+];
+
+/// A symbol put together by `compose/symbol.template.svg` from a frame and a Fachzeichen.
+#[derive(Debug, Deserialize, Clone, Default, PartialEq, Eq, Hash)]
+pub(crate) struct Composition {
+    /// `einheit`, `fahrzeug`, `stelle` or `gebaeude`, drawn by `compose/rahmen.template.xml`
+    pub(crate) rahmen: String,
+    /// An svg below the static directory or, ending in `.template.svg`, a template rendered with the context of the symbol
+    #[serde(default)]
+    pub(crate) fachzeichen: String,
+}
+
+/// The `status` of a symbol, passed to the templates by its name.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Status {
+    /// Dashed frame
+    Geplant,
+    /// Dashed frame
+    Vermutet,
+    /// Struck through
+    Ausgefallen,
+}
+
+impl Status {
+    pub(crate) const ALL: [Status; 3] = [
+        Status::Geplant,
+        Status::Vermutet,
+        Status::Ausgefallen,
+    ];
+
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Status::Geplant => "geplant",
+            Status::Vermutet => "vermutet",
+            Status::Ausgefallen => "ausgefallen",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub(crate) struct Organisation {
+    /// Output directory, e.g. `THW`
+    pub(crate) name: String,
+    /// Key of the symbol list in the catalogue, defaults to the lowercase name
+    pub(crate) key: Option<String>,
+    /// Human readable name, defaults to the name
+    pub(crate) display: Option<String>,
+    /// Label rendered into the symbol, defaults to the uppercase name
+    pub(crate) label: Option<String>,
+    #[serde(default = "default_main_color")]
+    pub(crate) main_color: String,
+    #[serde(default = "default_secondary_color")]
+    pub(crate) secondary_color: String,
+}
+
+impl Organisation {
+    pub(crate) fn unknown(name: &str) -> Organisation {
+        Organisation {
+            name: name.to_string(),
+            key: None,
+            display: None,
+            label: None,
+            main_color: default_main_color(),
+            secondary_color: default_secondary_color(),
+        }
+    }
+
+    pub(crate) fn key(&self) -> String {
+        self.key
+            .clone()
+            .unwrap_or_else(|| self.name.to_lowercase())
+    }
+
+    pub(crate) fn display(&self) -> &str {
+        self.display
+            .as_deref()
+            .unwrap_or(&self.name)
+    }
+
+    pub(crate) fn label(&self) -> String {
+        self.label
+            .clone()
+            .unwrap_or_else(|| self.name.to_uppercase())
+    }
+}
+
+fn default_main_color() -> String {
+    "#fff".to_string()
+}
+
+fn default_secondary_color() -> String {
+    "#000".to_string()
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub(crate) struct DescriptionObjects {
     pub(crate) template: String,
     pub(crate) zug: String,
-    pub(crate) names: String,
-    pub(crate) special: String,
+    pub(crate) names: Labels,
+    /// Also generates the symbol without a name
+    #[serde(default)]
+    pub(crate) unlabeled: bool,
+    #[serde(default)]
+    pub(crate) special: Labels,
+    /// Location line below the symbol, one symbol per Ort
+    #[serde(default)]
+    pub(crate) ort: Labels,
+    /// Unit size drawn by `groesse.template.xml` above the frame, one symbol per size
+    #[serde(default)]
+    pub(crate) groesse: Labels,
+    #[serde(flatten)]
+    pub(crate) modifiers: Modifiers,
+    /// Added to the `<desc>` of every symbol of the entry
+    #[serde(default)]
+    pub(crate) keywords: Vec<String>,
+    /// Composes the symbol instead of rendering the template, which then only names the output
+    #[serde(default)]
+    pub(crate) compose: Option<Composition>,
     pub(crate) dir: String,
+    #[serde(skip)]
+    pub(crate) source: Source,
 }
 
+impl DescriptionObjects {
+    pub(crate) fn names(&self) -> Vec<String> {
+        let mut names = self.names.variants();
+        if self.unlabeled && !names.iter().any(String::is_empty) {
+            names.insert(0, String::new());
+        }
+        names
+    }
+}
 
-#[derive(Debug, Deserialize, Clone)]
+/// A list of labels, written as TOML array or as the legacy comma separated string.
+///
+/// An empty entry stands for the unlabeled variant, `["", "A", "B"]` or in the legacy form a leading one (`",A,B"`).
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Labels {
+    pub(crate) values: Vec<String>,
+    pub(crate) legacy: bool,
+}
+
+impl Labels {
+    /// The values to generate, an empty list yields a single unlabeled variant.
+    pub(crate) fn variants(&self) -> Vec<String> {
+        if self.values.is_empty() {
+            vec![String::new()]
+        } else {
+            self.values.clone()
+        }
+    }
+
+    /// Duplicate and blank entries.
+    ///
+    /// A single `""` in a TOML array is the unlabeled variant, in the legacy form only the leading empty entry is.
+    pub(crate) fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        for (index, value) in self.values.iter().enumerate() {
+            let unlabeled = value.is_empty() && (!self.legacy || index == 0);
+            if value.trim().is_empty() && !unlabeled {
+                problems.push(format!("empty entry at position {}", index + 1));
+            } else if self.values[..index].contains(value) {
+                problems.push(format!("duplicate entry {:?}", value));
+            }
+        }
+        problems
+    }
+}
+
+impl<'de> Deserialize<'de> for Labels {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Legacy(String),
+            List(Vec<String>),
+        }
+
+        Ok(match Raw::deserialize(deserializer)? {
+            Raw::Legacy(text) => Labels {
+                values: text.split(',').map(str::to_string).collect(),
+                legacy: true,
+            },
+            Raw::List(values) => Labels {
+                values,
+                legacy: false,
+            },
+        })
+    }
+}
+
+
+/// The optional DV 102 modifiers around the symbol, passed to the templates as they are.
+#[derive(Debug, Deserialize, Clone, Default, PartialEq, Eq, Hash)]
+#[serde(default)]
+pub(crate) struct Modifiers {
+    /// Stärkeangabe, e.g. `1/2/9/12`
+    pub(crate) staerke: String,
+    /// Zeitangabe, e.g. `151200jan26`
+    pub(crate) zeit: String,
+    pub(crate) herkunft: String,
+    pub(crate) einsatzbereitschaft: String,
+    pub(crate) funkrufname: String,
+    /// One of [`Status`], empty for an existing unit
+    pub(crate) status: String,
+}
+
+impl Modifiers {
+    pub(crate) fn fields(&self) -> [(&'static str, &String); 6] {
+        [
+            ("staerke", &self.staerke),
+            ("zeit", &self.zeit),
+            ("herkunft", &self.herkunft),
+            ("einsatzbereitschaft", &self.einsatzbereitschaft),
+            ("funkrufname", &self.funkrufname),
+            ("status", &self.status),
+        ]
+    }
+
+    pub(crate) fn problems(&self) -> Vec<String> {
+        if self.status.is_empty() || Status::ALL.iter().any(|status| status.name() == self.status) {
+            Vec::new()
+        } else {
+            vec![format!(
+                "status {:?} is none of {}",
+                self.status,
+                Status::ALL.map(|status| status.name()).join(", ")
+            )]
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
 pub(crate) struct VolunteerConfig {
     pub(crate) enabled: bool,
     pub(crate) personen: Option<Vec<Person>>,
@@ -43,9 +634,81 @@ pub(crate) struct VolunteerConfig {
 
 #[derive(Debug, Deserialize, Clone)]
 pub(crate) struct Person {
-    pub(crate) volunteer: String,
+    pub(crate) volunteer: Labels,
     pub(crate) organisation: String,
     pub(crate) zug: String,
     pub(crate) template: String,
-    pub(crate) value: String,
-}
\ No newline at end of file
+    pub(crate) value: Labels,
+    #[serde(default)]
+    pub(crate) ort: Labels,
+    #[serde(flatten)]
+    pub(crate) modifiers: Modifiers,
+    /// Added to the `<desc>` of every symbol of the entry
+    #[serde(default)]
+    pub(crate) keywords: Vec<String>,
+    #[serde(skip)]
+    pub(crate) source: Source,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Deserialize)]
+    struct Entry {
+        labels: Labels,
+    }
+
+    fn labels(toml: &str) -> Labels {
+        toml::from_str::<Entry>(toml).unwrap().labels
+    }
+
+    #[test]
+    fn legacy_string_is_split_at_commas() {
+        let labels = labels(r#"labels = ",A,B""#);
+        assert_eq!(labels.values, ["", "A", "B"]);
+        assert!(labels.legacy);
+        assert!(labels.problems().is_empty());
+    }
+
+    #[test]
+    fn array_is_taken_as_is() {
+        let labels = labels(r#"labels = ["A", "B, C"]"#);
+        assert_eq!(labels.values, ["A", "B, C"]);
+        assert!(!labels.legacy);
+        assert_eq!(labels.variants(), ["A", "B, C"]);
+    }
+
+    #[test]
+    fn empty_list_yields_the_unlabeled_variant() {
+        assert_eq!(labels("labels = []").variants(), [""]);
+        assert_eq!(Labels::default().variants(), [""]);
+    }
+
+    #[test]
+    fn empty_string_in_array_is_the_unlabeled_variant() {
+        let labels = labels(r#"labels = ["A", ""]"#);
+        assert!(labels.problems().is_empty());
+        assert_eq!(labels.variants(), ["A", ""]);
+    }
+
+    #[test]
+    fn blank_and_duplicate_entries_are_reported() {
+        assert_eq!(labels(r#"labels = ["A", " "]"#).problems(), ["empty entry at position 2"]);
+        assert_eq!(labels(r#"labels = ["", "A", ""]"#).problems(), ["duplicate entry \"\""]);
+        assert_eq!(labels(r#"labels = ["A", "B", "A"]"#).problems(), ["duplicate entry \"A\""]);
+        assert_eq!(labels(r#"labels = "A,,B""#).problems(), ["empty entry at position 2"]);
+        assert_eq!(labels(r#"labels = "A,B,A""#).problems(), ["duplicate entry \"A\""]);
+    }
+
+    #[test]
+    fn included_files_cant_set_build_settings() {
+        let path = Path::new("catalogue/thw.toml");
+        assert!(check_build_settings(path, "include = [\"more\"]\n\n[[thw]]\ntemplate = \"Gruppe\"").is_ok());
+        let error = check_build_settings(path, "enable_png = true\n\n[raster]\nsizes = [16]").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "catalogue/thw.toml: enable_png, raster can only be set in the main catalogue file, not in an included one",
+        );
+    }
+}
@@ -0,0 +1,149 @@
+use std::{
+    error::Error as _,
+    io,
+    path::{Path, PathBuf},
+};
+
+use thiserror::Error;
+
+use crate::config::Source;
+
+/// Errors of the libraries used, boxed so their versions don't become part of the api.
+type BoxedError = Box<dyn std::error::Error + Send + Sync>;
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("{}: {source}", path.display())]
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    #[error("{}: {source}", path.display())]
+    Config {
+        path: PathBuf,
+        source: BoxedError,
+    },
+    #[error("template error")]
+    Template(#[source] BoxedError),
+    #[error("{0}")]
+    Invalid(String),
+    #[error("template {0} not found")]
+    MissingTemplate(String),
+    #[error("invalid svg")]
+    Svg(#[source] BoxedError),
+    #[error("{0}")]
+    Raster(String),
+    #[error("image encoding failed")]
+    Image(#[source] BoxedError),
+    #[error("json error")]
+    Json(#[source] BoxedError),
+    #[error("watching for changes failed")]
+    Watch(#[source] BoxedError),
+    #[error("couldn't listen on {address}")]
+    Serve {
+        address: String,
+        source: BoxedError,
+    },
+}
+
+macro_rules! boxed_from {
+    ($($source:ty => $variant:ident,)*) => {
+        $(
+            impl From<$source> for Error {
+                fn from(source: $source) -> Error {
+                    Error::$variant(Box::new(source))
+                }
+            }
+        )*
+    };
+}
+
+boxed_from! {
+    tera::Error => Template,
+    resvg::usvg::Error => Svg,
+    image::ImageError => Image,
+    serde_json::Error => Json,
+    notify::Error => Watch,
+}
+
+impl Error {
+    pub(crate) fn io(path: &Path, source: io::Error) -> Error {
+        Error::Io { path: path.to_path_buf(), source }
+    }
+
+    /// The message of this error followed by all of its causes.
+    pub fn report(&self) -> String {
+        let mut message = self.to_string();
+        let mut source = self.source();
+        while let Some(cause) = source {
+            let cause_message = cause.to_string();
+            if !message.ends_with(&cause_message) {
+                message.push_str(": ");
+                message.push_str(&cause_message);
+            }
+            source = cause.source();
+        }
+        message
+    }
+}
+
+/// A single output which couldn't be produced or a problem in the catalogue.
+#[derive(Debug)]
+pub(crate) struct Failure {
+    pub(crate) file: PathBuf,
+    pub(crate) line: Option<usize>,
+    pub(crate) template: Option<String>,
+    pub(crate) error: Error,
+}
+
+impl Failure {
+    pub(crate) fn new(file: &Path, template: Option<&str>, error: Error) -> Failure {
+        Failure {
+            file: file.to_path_buf(),
+            line: None,
+            template: template.map(str::to_string),
+            error,
+        }
+    }
+
+    /// A problem of the catalogue entry declared at `source`.
+    pub(crate) fn at(source: &Source, template: Option<&str>, error: Error) -> Failure {
+        Failure {
+            file: source.file.clone(),
+            line: source.line,
+            template: template.map(str::to_string),
+            error,
+        }
+    }
+
+    fn location(&self) -> String {
+        Source {
+            file: self.file.clone(),
+            line: self.line,
+        }.to_string()
+    }
+}
+
+pub(crate) fn print_report(failures: &[Failure]) {
+    if failures.is_empty() {
+        return;
+    }
+    eprintln!();
+    eprintln!("{} failure(s):", failures.len());
+    failures.iter().for_each(|failure| {
+        match &failure.template {
+            Some(template) => eprintln!(
+                "  {} [{}]: {}",
+                failure.location(),
+                template,
+                failure.error.report()
+            ),
+            None => eprintln!(
+                "  {}: {}",
+                failure.location(),
+                failure.error.report()
+            ),
+        }
+    });
+}
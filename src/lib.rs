@@ -0,0 +1,578 @@
+//! Generates tactical symbols (taktische Zeichen) from svg templates and a toml catalogue.
+//!
+//! The `taktische-zeichen` binary parses its arguments into a [`Command`] and [`Paths`] and hands them
+//! to [`run`]; other tools can render single symbols with a [`SymbolRenderer`].
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    thread::{
+        self,
+        JoinHandle,
+    },
+    time::{Duration, Instant},
+};
+
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use rayon::iter::{
+    IntoParallelRefIterator,
+    ParallelIterator,
+};
+use serde::{Deserialize, Serialize};
+use tera::Tera;
+
+use crate::config::{CollisionMode, Config, VolunteerConfig};
+use crate::error::{print_report, Failure};
+use crate::manifest::{Manifest, OutputEntry};
+use crate::templates::{load_templates, template_hashes, TemplateHash};
+use crate::plan::{Plan, StaticJob, SymbolJob};
+use crate::svg_tools::set_description;
+use crate::utils::{
+    calc_hash,
+    create_progress_bar,
+    file_to_base64,
+    hash_str,
+    map_entries,
+    path_to_id,
+    path_to_title,
+    process_entries,
+    save_to_file,
+};
+
+mod error;
+mod lint;
+mod manifest;
+mod renderer;
+mod svg_tools;
+mod config;
+mod plan;
+mod prune;
+mod serve;
+mod templates;
+mod utils;
+mod validate;
+mod watch;
+
+pub use crate::config::RasterFormat;
+pub use crate::error::Error;
+pub use crate::renderer::{SymbolRenderer, SymbolSpec};
+
+/// Where the catalogues, templates and static files are read from and the output is written to.
+#[derive(Debug, Clone)]
+pub struct Paths {
+    /// Symbol catalogue, e.g. `config.toml`
+    pub config: PathBuf,
+    /// Person / volunteer catalogue, `volunteer.toml` is used if it exists
+    pub volunteer: Option<PathBuf>,
+    /// Root directory of the `*.template.svg` files
+    pub templates: PathBuf,
+    /// Root directory of the static svg files
+    pub static_dir: PathBuf,
+    pub output: PathBuf,
+}
+
+/// The subcommands of the binary, see its `--help` for what they do.
+#[derive(Debug, Clone)]
+pub enum Command {
+    Build { no_png: bool },
+    Render,
+    Png,
+    Drawio,
+    List,
+    Validate,
+    Lint,
+    Clean,
+    Watch { no_png: bool },
+    Serve { address: String },
+    Prune { dry_run: bool },
+}
+
+/// Runs a command, printing progress and failures.
+pub fn run(
+    command: Command,
+    paths: &Paths,
+) -> ExitCode {
+    let result = match command {
+        Command::Build { no_png } => build(paths, no_png),
+        Command::Render => render(paths),
+        Command::Png => png(paths),
+        Command::Drawio => drawio(paths),
+        Command::List => list(paths),
+        Command::Validate => validate(paths),
+        Command::Lint => lint(paths),
+        Command::Clean => clean(paths),
+        Command::Watch { no_png } => watch(paths, no_png),
+        Command::Serve { address } => serve(paths, &address),
+        Command::Prune { dry_run } => prune(paths, dry_run),
+    };
+
+    match result {
+        Ok(failures) if failures.is_empty() => ExitCode::SUCCESS,
+        Ok(failures) => {
+            print_report(&failures);
+            ExitCode::FAILURE
+        }
+        Err(error) => {
+            eprintln!("Error: {}", error.report());
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn spinner_style() -> ProgressStyle {
+    ProgressStyle::with_template("{prefix:.bold.dim}[{pos:4} files][{elapsed:3}] {spinner} {wide_msg}")
+        .unwrap()
+        .tick_chars("⠁⠂⠄⡀⢀⠠⠐⠈ ")
+}
+
+fn build(paths: &Paths, no_png: bool) -> Result<Vec<Failure>, Error> {
+    build_with(paths, no_png, MultiProgress::new())
+}
+
+fn build_with(
+    paths: &Paths,
+    no_png: bool,
+    m: MultiProgress,
+) -> Result<Vec<Failure>, Error> {
+    let (cfg, volunteer_config) = config::parse(&paths.config, paths.volunteer.as_deref())?;
+    let enable_png = cfg.enable_png && !no_png;
+
+    let plan = match checked_plan(paths, &cfg, &volunteer_config) {
+        Ok(plan) => plan,
+        Err(failures) => return Ok(failures),
+    };
+    let previous = Manifest::load(&paths.output);
+    let (mut failures, mut manifest, mut m) = render_all(paths, &plan, &previous, m)?;
+    manifest.carry_over(&previous);
+
+    let spinner_style = spinner_style();
+    if enable_png {
+        let pb;
+        (pb, m) = create_progress_bar(&spinner_style, m, "png", false);
+        failures.extend(svg_tools::convert_svg(pb, &paths.output, &cfg.raster, &mut manifest));
+    }
+    manifest.save(&paths.output)?;
+    let (pb, _m) = create_progress_bar(&spinner_style, m, "drawio", false);
+
+    failures.extend(create_drawio(pb, &paths.output)?);
+    Ok(failures)
+}
+
+fn render(paths: &Paths) -> Result<Vec<Failure>, Error> {
+    let (cfg, volunteer_config) = config::parse(&paths.config, paths.volunteer.as_deref())?;
+    let plan = match checked_plan(paths, &cfg, &volunteer_config) {
+        Ok(plan) => plan,
+        Err(failures) => return Ok(failures),
+    };
+    let previous = Manifest::load(&paths.output);
+    let (failures, mut manifest, _m) = render_all(paths, &plan, &previous, MultiProgress::new())?;
+    manifest.carry_over(&previous);
+    manifest.save(&paths.output)?;
+    Ok(failures)
+}
+
+/// Plans the run and handles colliding outputs according to the `collisions` setting.
+fn checked_plan(
+    paths: &Paths,
+    cfg: &Config,
+    volunteer_config: &VolunteerConfig,
+) -> Result<Plan, Vec<Failure>> {
+    let mut plan = Plan::new(&paths.output, &paths.static_dir, cfg, volunteer_config);
+    let collisions = plan.collisions();
+    if cfg.collisions == CollisionMode::Error && !collisions.is_empty() {
+        return Err(collisions
+            .iter()
+            .map(|collision| Failure::at(&collision.source, None, Error::Invalid(collision.message())))
+            .collect());
+    }
+    collisions.iter().for_each(|collision| {
+        eprintln!("warning: {}: {}", collision.source, collision.message());
+    });
+    plan.drop_duplicates();
+    Ok(plan)
+}
+
+/// Rasterises every svg in the output directory again.
+fn png(paths: &Paths) -> Result<Vec<Failure>, Error> {
+    let (cfg, _) = config::parse(&paths.config, paths.volunteer.as_deref())?;
+    let mut failures = Vec::new();
+    let mut manifest = Manifest::load(&paths.output);
+    manifest.outputs.retain(|path, _| paths.output.join(path).is_file());
+    for svg_path in map_entries(&paths.output) {
+        match calc_hash(&svg_path) {
+            Ok(svg) => {
+                let entry = manifest.entry(&paths.output, &svg_path);
+                entry.svg = svg;
+                entry.raster = None;
+            }
+            Err(error) => failures.push(Failure::new(&svg_path, None, error)),
+        }
+    }
+
+    let (pb, _m) = create_progress_bar(&spinner_style(), MultiProgress::new(), "png", false);
+    failures.extend(svg_tools::convert_svg(pb, &paths.output, &cfg.raster, &mut manifest));
+    manifest.save(&paths.output)?;
+    Ok(failures)
+}
+
+fn drawio(paths: &Paths) -> Result<Vec<Failure>, Error> {
+    let (pb, _m) = create_progress_bar(&spinner_style(), MultiProgress::new(), "drawio", false);
+    create_drawio(pb, &paths.output)
+}
+
+fn list(paths: &Paths) -> Result<Vec<Failure>, Error> {
+    let (cfg, volunteer_config) = config::parse(&paths.config, paths.volunteer.as_deref())?;
+    let plan = Plan::new(&paths.output, &paths.static_dir, &cfg, &volunteer_config);
+
+    plan.symbols
+        .iter()
+        .for_each(|(organisation, jobs)| {
+            println!("# {} ({})", organisation.display(), organisation.name);
+            jobs.iter().for_each(|job| println!("{}", job.target.display()));
+        });
+    if !plan.volunteers.is_empty() {
+        println!("# volunteer");
+        plan.volunteers.iter().for_each(|job| println!("{}", job.target.display()));
+    }
+    println!("# static");
+    plan.statics.iter().for_each(|job| println!("{}", job.target.display()));
+    Ok(Vec::new())
+}
+
+fn validate(paths: &Paths) -> Result<Vec<Failure>, Error> {
+    let (cfg, volunteer_config) = config::parse(&paths.config, paths.volunteer.as_deref())?;
+    let (template_engine, mut failures) = load_templates(&paths.templates)?;
+
+    let plan = Plan::new(&paths.output, &paths.static_dir, &cfg, &volunteer_config);
+    failures.extend(validate::validate(
+        &paths.templates,
+        &paths.static_dir,
+        &cfg,
+        &volunteer_config,
+        &plan,
+        &template_engine,
+    ));
+
+    if failures.is_empty() {
+        println!("OK");
+    }
+    Ok(failures)
+}
+
+fn lint(paths: &Paths) -> Result<Vec<Failure>, Error> {
+    let (template_engine, mut failures) = load_templates(&paths.templates)?;
+    failures.extend(lint::lint(&paths.templates, &template_engine));
+
+    if failures.is_empty() {
+        println!("OK");
+    }
+    Ok(failures)
+}
+
+fn clean(paths: &Paths) -> Result<Vec<Failure>, Error> {
+    prune::clean(&paths.output)?;
+    Ok(Vec::new())
+}
+
+fn prune(paths: &Paths, dry_run: bool) -> Result<Vec<Failure>, Error> {
+    let (cfg, volunteer_config) = config::parse(&paths.config, paths.volunteer.as_deref())?;
+    let plan = Plan::new(&paths.output, &paths.static_dir, &cfg, &volunteer_config);
+    let stale = prune::stale_outputs(&paths.output, &plan, &cfg.raster);
+
+    if dry_run {
+        stale.iter().for_each(|path| println!("would remove {}", path.display()));
+        return Ok(Vec::new());
+    }
+    let mut failures = prune::remove(&paths.output, &stale);
+
+    let mut manifest = Manifest::load(&paths.output);
+    if !manifest.outputs.is_empty() {
+        manifest.outputs.retain(|path, _| paths.output.join(path).is_file());
+        manifest.save(&paths.output)?;
+    }
+
+    // the libraries still list the removed symbols
+    if !stale.is_empty() && paths.output.join("drawio").is_dir() {
+        let (pb, _m) = create_progress_bar(&spinner_style(), MultiProgress::new(), "drawio", false);
+        failures.extend(create_drawio(pb, &paths.output)?);
+    }
+    Ok(failures)
+}
+
+fn watch(paths: &Paths, no_png: bool) -> Result<Vec<Failure>, Error> {
+    rebuild(paths, no_png);
+    watch::watch(paths, |changed| {
+        changed.iter().for_each(|path| println!("changed {}", path.display()));
+        rebuild(paths, no_png);
+    })?;
+    Ok(Vec::new())
+}
+
+fn serve(paths: &Paths, address: &str) -> Result<Vec<Failure>, Error> {
+    serve::serve(address, paths)?;
+    Ok(Vec::new())
+}
+
+/// An incremental build without progress bars, logging what it wrote.
+fn rebuild(paths: &Paths, no_png: bool) {
+    let start = Instant::now();
+    let before = Manifest::load(&paths.output);
+    let result = build_with(paths, no_png, MultiProgress::with_draw_target(ProgressDrawTarget::hidden()));
+    let after = Manifest::load(&paths.output);
+
+    let svgs = after.outputs
+        .iter()
+        .filter(|(path, entry)| before.outputs.get(*path).map(|old| &old.svg) != Some(&entry.svg))
+        .count();
+    let rasters = after.outputs
+        .iter()
+        .filter(|(path, entry)| {
+            entry.raster.is_some() && before.outputs.get(*path).map(|old| (&old.svg, &old.raster)) != Some((&entry.svg, &entry.raster))
+        })
+        .count();
+    match result {
+        Ok(failures) => {
+            println!(
+                "rebuilt {} svg(s), rasterised {} in {:.1}s",
+                svgs,
+                rasters,
+                start.elapsed().as_secs_f32()
+            );
+            print_report(&failures);
+        }
+        Err(error) => eprintln!("Error: {}", error.report()),
+    }
+}
+
+/// Renders the catalogue, the volunteers and copies the static files.
+///
+/// Symbols whose template, dependencies and context are unchanged since the `previous` build are
+/// left alone. The returned manifest lists every svg of the plan.
+fn render_all(
+    paths: &Paths,
+    plan: &Plan,
+    previous: &Manifest,
+    m: MultiProgress,
+) -> Result<(Vec<Failure>, Manifest, MultiProgress), Error> {
+    let (template_engine, mut failures) = load_templates(&paths.templates)?;
+    let template_hashes = template_hashes(&paths.templates);
+    let mut manifest = Manifest::new();
+
+    let spinner_style = spinner_style();
+    let mut m = m;
+
+    let handler: JoinHandle<Generated> = if !plan.volunteers.is_empty() {
+        let template_engine_clone = template_engine.clone();
+        let template_hashes_clone = template_hashes.clone();
+        let output = paths.output.clone();
+        let previous = previous.clone();
+        let pb;
+        (pb, m) = create_progress_bar(&spinner_style, m, "volunteer", false);
+
+        let jobs = plan.volunteers.clone();
+        thread::spawn(move || generate_svg(
+            pb,
+            &jobs,
+            &template_engine_clone,
+            &template_hashes_clone,
+            &output,
+            &previous,
+        ))
+    } else {
+        thread::spawn(Generated::default)
+    };
+    let generated: Vec<Generated> = plan.symbols
+        .par_iter()
+        .map(|(organisation, jobs)| {
+            let pb = m.add(ProgressBar::new_spinner());
+            pb.set_style(spinner_style.clone());
+            pb.set_prefix(format!("[{:>7}]", organisation.name));
+            pb.enable_steady_tick(Duration::from_millis(100));
+
+            generate_svg(
+                pb.clone(),
+                jobs,
+                &template_engine,
+                &template_hashes,
+                &paths.output,
+                previous,
+            )
+        })
+        .collect();
+
+    let pb;
+    (pb, m) = create_progress_bar(&spinner_style, m, "static", true);
+    let statics = plan.statics.clone();
+    let handler2 = thread::spawn(move || copy_static(pb, &statics));
+
+    let generated = generated
+        .into_iter()
+        .chain([
+            handler.join().expect("volunteer thread panicked"),
+            handler2.join().expect("static thread panicked"),
+        ]);
+    for (errors, entries) in generated {
+        failures.extend(errors);
+        entries
+            .into_iter()
+            .for_each(|(target, entry)| manifest.insert(&paths.output, &target, entry));
+    }
+    Ok((failures, manifest, m))
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct DrawIoLibEntry {
+    data: String,
+    w: i32,
+    h: i32,
+    title: String,
+    aspect: String,
+}
+
+fn create_drawio(
+    pb: ProgressBar,
+    output: &Path,
+) -> Result<Vec<Failure>, Error> {
+    let mut failures = Vec::new();
+    let mut data: HashMap<String, Vec<DrawIoLibEntry>> = HashMap::new();
+    process_entries(output, |path: PathBuf| {
+        let content = match file_to_base64(&path) {
+            Ok(content) => content,
+            Err(error) => {
+                failures.push(Failure::new(&path, None, Error::io(&path, error)));
+                return;
+            }
+        };
+        let entry = DrawIoLibEntry {
+            data: format!(
+                "data:image/svg+xml;base64,{}",
+                content
+            ),
+            w: 256,
+            h: 256,
+            title: path_to_title(output, &path),
+            aspect: "fixed".to_string(),
+        };
+        let map_id = path_to_id(output, path.parent().unwrap_or(output));
+        data.entry(map_id).or_default().push(entry);
+    });
+
+    let drawio_dir = output.join("drawio");
+    fs::create_dir_all(&drawio_dir)
+        .map_err(|e| Error::io(&drawio_dir, e))?;
+
+    for (key, item) in data.iter() {
+        let json_string = serde_json::to_string(item)?;
+        let target = drawio_dir.join(format!("{}.xml", key));
+
+        let content = format!("<mxlibrary>{}</mxlibrary>", &json_string);
+
+        pb.inc(1);
+        // unchanged libraries keep their modification time
+        if fs::read_to_string(&target).is_ok_and(|existing| existing == content) {
+            continue;
+        }
+        pb.set_message(format!("Save to {}", target.display()));
+        if let Err(error) = save_to_file(&target, &content) {
+            failures.push(Failure::new(&target, None, error));
+        }
+    }
+    pb.finish_with_message("finished");
+    Ok(failures)
+}
+
+
+pub(crate) fn copy_static(
+    pb: ProgressBar,
+    statics: &[StaticJob],
+) -> Generated {
+    let mut failures = Vec::new();
+    let mut entries = Vec::new();
+    for job in statics {
+        let old_svg_path = &job.source.file;
+        match copy_static_file(job).and_then(|_| calc_hash(&job.target)) {
+            Ok(svg) => entries.push((job.target.clone(), OutputEntry { svg, ..OutputEntry::default() })),
+            Err(error) => failures.push(Failure::new(old_svg_path, None, error)),
+        }
+
+        pb.inc(1);
+        pb.set_message(format!("Copied: {} -> {}", old_svg_path.display(), job.target.display()));
+    }
+    pb.finish_with_message("finished");
+    (failures, entries)
+}
+
+/// Copies a static svg with the title, description and metadata of its path.
+fn copy_static_file(job: &StaticJob) -> Result<(), Error> {
+    let path = &job.source.file;
+    let svg = fs::read_to_string(path).map_err(|e| Error::io(path, e))?;
+    let metadata = serde_json::to_string(&job.metadata(&svg))?;
+    save_to_file(&job.target, &set_description(&svg, &job.title(), &job.description(), &metadata)?)
+}
+
+/// Failures and manifest entries of the written svgs.
+type Generated = (Vec<Failure>, Vec<(PathBuf, OutputEntry)>);
+
+fn generate_svg(
+    pb: ProgressBar,
+    jobs: &[SymbolJob],
+    tera: &Tera,
+    template_hashes: &HashMap<String, TemplateHash>,
+    output: &Path,
+    previous: &Manifest,
+) -> Generated {
+    let mut failures = Vec::new();
+    let mut entries = Vec::new();
+    let mut drawn_statuses = HashMap::new();
+    for job in jobs {
+        pb.set_message(format!("Processed content of  {}", job.target.display()));
+        pb.inc(1);
+        let previous = previous.get(output, &job.target);
+        match process_file_common(job, tera, template_hashes, previous, &mut drawn_statuses) {
+            Ok(entry) => entries.push((job.target.clone(), entry)),
+            Err(error) => failures.push(Failure::new(&job.target, Some(&job.template_name()), error)),
+        }
+    }
+    pb.finish_with_message("finished");
+    (failures, entries)
+}
+
+fn process_file_common(
+    job: &SymbolJob,
+    tera: &Tera,
+    template_hashes: &HashMap<String, TemplateHash>,
+    previous: Option<&OutputEntry>,
+    drawn_statuses: &mut HashMap<(String, String), bool>,
+) -> Result<OutputEntry, Error> {
+    let template = job
+        .resolve_template(tera)
+        .ok_or_else(|| Error::MissingTemplate(job.template_name()))?;
+    let template_hash = template_hashes.get(&template);
+
+    let mut entry = OutputEntry {
+        svg: String::new(),
+        template_hash: template_hash.map(|hash| hash.hash.clone()),
+        dependencies: template_hash.map(|hash| hash.dependencies.clone()).unwrap_or_default(),
+        template: Some(template.clone()),
+        context: job.context(tera)?,
+        raster: None,
+    };
+    if let Some(previous) = previous {
+        if entry.template_hash.is_some()
+            && previous.template == entry.template
+            && previous.template_hash == entry.template_hash
+            && previous.context == entry.context
+            && job.target.is_file()
+        {
+            return Ok(previous.clone());
+        }
+    }
+
+    job.check_status(tera, &template, &entry.context, drawn_statuses)?;
+    let content = job.render_symbol(tera, &template, &entry.context, entry.template_hash.as_deref())?;
+    save_to_file(&job.target, &content)?;
+    entry.svg = hash_str(&content);
+    Ok(entry)
+}
@@ -0,0 +1,261 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::Path,
+};
+
+use resvg::usvg::{self, NodeExt, NodeKind, TreeParsing};
+use tera::{Context, Tera};
+
+use crate::config::{Modifiers, Status};
+use crate::error::{Error, Failure};
+use crate::svg_tools::{parse_xml, root_title};
+use crate::templates::{draws_status, template_hashes};
+
+/// Elements and attributes svg spells in camelCase, other tools than resvg ignore them in any other casing.
+const CAMEL_CASE_NAMES: [&str; 30] = [
+    "viewBox",
+    "preserveAspectRatio",
+    "clipPath",
+    "clipPathUnits",
+    "linearGradient",
+    "radialGradient",
+    "gradientUnits",
+    "gradientTransform",
+    "spreadMethod",
+    "patternUnits",
+    "patternContentUnits",
+    "patternTransform",
+    "maskUnits",
+    "maskContentUnits",
+    "markerWidth",
+    "markerHeight",
+    "markerUnits",
+    "refX",
+    "refY",
+    "filterUnits",
+    "primitiveUnits",
+    "stdDeviation",
+    "feFlood",
+    "feComposite",
+    "feGaussianBlur",
+    "feColorMatrix",
+    "feOffset",
+    "textPath",
+    "textLength",
+    "lengthAdjust",
+];
+
+/// Renders every `*.template.svg` with sample values and checks the resulting svg for problems
+/// resvg tolerates but other tools don't.
+pub(crate) fn lint(
+    templates: &Path,
+    tera: &Tera,
+) -> Vec<Failure> {
+    let context = sample_context();
+    let hashes = template_hashes(templates);
+    let mut failures = Vec::new();
+    // title -> template file defining it -> templates rendering it
+    let mut titles: BTreeMap<String, BTreeMap<String, Vec<String>>> = BTreeMap::new();
+
+    let mut names: Vec<&str> = tera
+        .get_template_names()
+        .filter(|name| name.ends_with(".template.svg"))
+        .collect();
+    names.sort();
+
+    for name in names {
+        let file = templates.join(name);
+        let rendered = tera.render(name, &context).map_err(Error::from).and_then(|svg| {
+            let (mut problems, title) = check(&svg)?;
+            problems.extend(ignored_modifiers(tera, name, &svg));
+            Ok((problems, title))
+        });
+        let (problems, title) = match rendered {
+            Ok(checked) => checked,
+            Err(error) => {
+                failures.push(Failure::new(&file, Some(name), error));
+                continue;
+            }
+        };
+        failures.extend(problems.into_iter().map(|problem| Failure::new(&file, Some(name), Error::Invalid(problem))));
+
+        // a title coming from a shared template is meant to be shared
+        let Some(title) = title else { continue };
+        let dependencies = hashes.get(name).map(|hash| hash.dependencies.as_slice()).unwrap_or_default();
+        let source = std::iter::once(name)
+            .chain(dependencies.iter().map(String::as_str))
+            .find(|candidate| defines_title(&templates.join(candidate), &title));
+        if let Some(source) = source {
+            titles
+                .entry(title)
+                .or_default()
+                .entry(source.to_string())
+                .or_default()
+                .push(name.to_string());
+        }
+    }
+
+    for (title, sources) in titles.iter().filter(|(_, sources)| sources.len() > 1) {
+        for source in sources.keys() {
+            failures.push(Failure::new(
+                &templates.join(source),
+                Some(source),
+                Error::Invalid(format!("title {:?} is also used by {} other template(s)", title, sources.len() - 1)),
+            ));
+        }
+    }
+    failures
+}
+
+/// Values for every variable the templates use, non-empty so the optional parts are rendered as well.
+fn sample_context() -> Context {
+    let modifiers = sample_modifiers();
+    let context: BTreeMap<&str, &str> = [
+        ("value", "B"),
+        ("organisation", "THW"),
+        ("ort", "Musterstadt"),
+        ("groesse", ""),
+        ("volunteer", "Max Mustermann"),
+        ("special", "1"),
+        ("main_color", "#003399"),
+        ("secondary_color", "#ffffff"),
+        ("rahmen", "einheit"),
+        ("fachzeichen", ""),
+    ]
+        .into_iter()
+        .chain(modifiers.fields().map(|(key, value)| (key, value.as_str())))
+        .collect();
+    Context::from_serialize(context).unwrap_or_default()
+}
+
+fn sample_modifiers() -> Modifiers {
+    Modifiers {
+        staerke: "1/2/9/12".to_string(),
+        zeit: "151200jan26".to_string(),
+        herkunft: "Musterstadt".to_string(),
+        einsatzbereitschaft: "E".to_string(),
+        funkrufname: "Heros Musterstadt 10/1".to_string(),
+        status: Status::Ausgefallen.name().to_string(),
+    }
+}
+
+/// Modifiers the template drops, the catalogue could set them without any visible effect.
+fn ignored_modifiers(
+    tera: &Tera,
+    name: &str,
+    svg: &str,
+) -> Vec<String> {
+    let mut problems: Vec<String> = sample_modifiers()
+        .fields()
+        .into_iter()
+        .filter(|(key, _)| *key != "status")
+        .filter(|(key, _)| {
+            let mut context = sample_context();
+            context.insert(*key, "");
+            tera.render(name, &context).is_ok_and(|without| without == svg)
+        })
+        .map(|(key, _)| format!("{} is not rendered", key))
+        .collect();
+    problems.extend(
+        Status::ALL
+            .iter()
+            .filter(|status| !draws_status(tera, name, &sample_context(), status.name()).unwrap_or(true))
+            .map(|status| format!("status {} is not drawn", status.name())),
+    );
+    problems
+}
+
+/// Problems of a rendered svg, along with its title.
+fn check(svg: &str) -> Result<(BTreeSet<String>, Option<String>), Error> {
+    let document = parse_xml(svg)?;
+    let mut problems = BTreeSet::new();
+
+    let mut ids = BTreeSet::new();
+    let mut references = BTreeSet::new();
+    for node in document.descendants().filter(|node| node.is_element()) {
+        let tag = node.tag_name().name();
+        problems.extend(check_casing("element", tag));
+        if tag == "style" {
+            references.extend(url_references(node.text().unwrap_or_default()));
+        }
+        for attribute in node.attributes() {
+            problems.extend(check_casing("attribute", attribute.name()));
+            match attribute.name() {
+                "id" if !ids.insert(attribute.value()) => {
+                    problems.insert(format!("duplicate id {:?}", attribute.value()));
+                }
+                "href" => references.extend(attribute.value().strip_prefix('#')),
+                _ => references.extend(url_references(attribute.value())),
+            }
+        }
+    }
+    problems.extend(
+        references
+            .iter()
+            .filter(|reference| !ids.contains(*reference))
+            .map(|reference| format!("undefined reference #{}", reference)),
+    );
+
+    let title = root_title(&document);
+    if title.is_none() {
+        problems.insert("missing <title>".to_string());
+    }
+
+    let tree = usvg::Tree::from_str(svg, &usvg::Options::default())?;
+    let view_box = tree.view_box.rect;
+    for node in tree.root.descendants() {
+        let NodeKind::Path(ref path) = *node.borrow() else { continue };
+        let Some(bbox) = node.calculate_bbox() else { continue };
+        if bbox.left() < view_box.left()
+            || bbox.top() < view_box.top()
+            || bbox.right() > view_box.right()
+            || bbox.bottom() > view_box.bottom()
+        {
+            let element = match path.id.as_str() {
+                "" => "shape".to_string(),
+                id => format!("shape #{}", id),
+            };
+            problems.insert(format!(
+                "{} reaches outside of the viewBox ({} {} {} {})",
+                element,
+                bbox.x(),
+                bbox.y(),
+                bbox.width(),
+                bbox.height(),
+            ));
+        }
+    }
+
+    Ok((problems, title))
+}
+
+fn check_casing(
+    kind: &str,
+    name: &str,
+) -> Option<String> {
+    CAMEL_CASE_NAMES
+        .iter()
+        .find(|known| known.eq_ignore_ascii_case(name) && **known != name)
+        .map(|known| format!("{} {} should be spelled {}", kind, name, known))
+}
+
+/// Ids referenced as `url(#id)`.
+fn url_references(value: &str) -> Vec<&str> {
+    value
+        .split("url(#")
+        .skip(1)
+        .filter_map(|rest| rest.split(')').next())
+        .map(str::trim)
+        .collect()
+}
+
+/// Whether the template file spells out `title` itself, either as `<title>` or as a block.
+fn defines_title(
+    file: &Path,
+    title: &str,
+) -> bool {
+    fs::read_to_string(file).is_ok_and(|content| {
+        content.contains(&format!(">{}<", title)) || content.contains(&format!("%}}{}{{%", title))
+    })
+}
@@ -0,0 +1,124 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::ResolvedRaster;
+use crate::error::Error;
+use crate::utils::save_to_file;
+
+const MANIFEST: &str = "manifest.json";
+
+/// What every output of the last build was made from, stored as `manifest.json` in the output directory.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(crate) struct Manifest {
+    pub(crate) generator: String,
+    /// Keyed by the path of the svg relative to the output directory
+    pub(crate) outputs: BTreeMap<PathBuf, OutputEntry>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub(crate) struct OutputEntry {
+    /// Hash of the written svg
+    pub(crate) svg: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) template: Option<String>,
+    /// Hash over the template and every template it includes, extends or imports
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) template_hash: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) dependencies: Vec<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) context: BTreeMap<String, String>,
+    /// Set once every raster of the svg has been written
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) raster: Option<RasterEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct RasterEntry {
+    /// Hash of the installed fonts
+    pub(crate) fonts: String,
+    pub(crate) settings: ResolvedRaster,
+}
+
+impl Manifest {
+    pub(crate) fn new() -> Manifest {
+        Manifest {
+            generator: env!("CARGO_PKG_VERSION").to_string(),
+            outputs: BTreeMap::new(),
+        }
+    }
+
+    /// The manifest of the last build, an empty one if there is none or it was written by another version.
+    pub(crate) fn load(output: &Path) -> Manifest {
+        let path = output.join(MANIFEST);
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(_) => return Manifest::new(),
+        };
+        match serde_json::from_str::<Manifest>(&content) {
+            Ok(manifest) if manifest.generator == env!("CARGO_PKG_VERSION") => manifest,
+            Ok(_) => Manifest::new(),
+            Err(error) => {
+                eprintln!("warning: {}: {}, rebuilding everything", path.display(), error);
+                Manifest::new()
+            }
+        }
+    }
+
+    /// Where the manifest of `output` is stored, its presence marks a directory written by a build.
+    pub(crate) fn path(output: &Path) -> PathBuf {
+        output.join(MANIFEST)
+    }
+
+    pub(crate) fn save(&self, output: &Path) -> Result<(), Error> {
+        save_to_file(&output.join(MANIFEST), &serde_json::to_string_pretty(self)?)
+    }
+
+    pub(crate) fn get(
+        &self,
+        output: &Path,
+        svg_path: &Path,
+    ) -> Option<&OutputEntry> {
+        self.outputs.get(&relative(output, svg_path))
+    }
+
+    pub(crate) fn insert(
+        &mut self,
+        output: &Path,
+        svg_path: &Path,
+        entry: OutputEntry,
+    ) {
+        self.outputs.insert(relative(output, svg_path), entry);
+    }
+
+    pub(crate) fn entry(
+        &mut self,
+        output: &Path,
+        svg_path: &Path,
+    ) -> &mut OutputEntry {
+        self.outputs.entry(relative(output, svg_path)).or_default()
+    }
+
+    /// Keeps the rasters of the previous build for every svg which is unchanged.
+    pub(crate) fn carry_over(&mut self, previous: &Manifest) {
+        for (path, entry) in self.outputs.iter_mut() {
+            if let Some(old) = previous.outputs.get(path) {
+                if old.svg == entry.svg {
+                    entry.raster.clone_from(&old.raster);
+                }
+            }
+        }
+    }
+}
+
+fn relative(
+    output: &Path,
+    path: &Path,
+) -> PathBuf {
+    path.strip_prefix(output).unwrap_or(path).to_path_buf()
+}
@@ -0,0 +1,681 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+use tera::{Context, Tera};
+use walkdir::WalkDir;
+
+use crate::config::{Composition, Config, DescriptionObjects, Modifiers, Organisation, Person, Source, Status, VolunteerConfig};
+use crate::error::Error;
+use crate::svg_tools::{parse_xml, root_title, set_description};
+use crate::templates::{draws_status, resolve_template, template_candidates};
+use crate::utils::{file_to_base64, hash_str, join_filename, join_paths, str_to_base64, uppercase_first_letter};
+
+/// A single svg file the catalogue produces.
+#[derive(Debug, Clone)]
+pub(crate) struct SymbolJob {
+    pub(crate) target: PathBuf,
+    pub(crate) organisation: Organisation,
+    pub(crate) template: String,
+    pub(crate) zug: String,
+    pub(crate) dir: String,
+    pub(crate) inverted: bool,
+    pub(crate) value: String,
+    pub(crate) special: String,
+    pub(crate) ort: String,
+    pub(crate) groesse: String,
+    pub(crate) volunteer: String,
+    pub(crate) modifiers: Modifiers,
+    pub(crate) keywords: Vec<String>,
+    pub(crate) composed: Option<Composed>,
+    pub(crate) source: Source,
+}
+
+/// The parts of a symbol declared with `compose`.
+#[derive(Debug, Clone)]
+pub(crate) struct Composed {
+    pub(crate) rahmen: String,
+    pub(crate) fachzeichen: Option<Fachzeichen>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) enum Fachzeichen {
+    File(PathBuf),
+    Template(String),
+}
+
+impl Composed {
+    pub(crate) fn new(
+        composition: &Composition,
+        static_dir: &Path,
+    ) -> Composed {
+        let fachzeichen = match composition.fachzeichen.as_str() {
+            "" => None,
+            name if name.ends_with(".template.svg") => Some(Fachzeichen::Template(name.to_string())),
+            name => Some(Fachzeichen::File(static_dir.join(name))),
+        };
+        Composed {
+            rahmen: composition.rahmen.clone(),
+            fachzeichen,
+        }
+    }
+}
+
+impl SymbolJob {
+    /// Name of the template as written in the catalogue.
+    pub(crate) fn template_name(&self) -> String {
+        match self.composed {
+            Some(_) => "compose/symbol.template.svg".to_string(),
+            None => format!("{}/{}.template.svg", self.dir, self.template),
+        }
+    }
+
+    /// Name of the template inside the template engine, if there is one.
+    pub(crate) fn resolve_template(&self, tera: &Tera) -> Option<String> {
+        let candidates = match self.composed {
+            Some(_) => template_candidates(&self.organisation.name, &self.zug, "compose", "symbol"),
+            None => template_candidates(&self.organisation.name, &self.zug, &self.dir, &self.template),
+        };
+        resolve_template(tera, &candidates)
+    }
+
+    /// The variables passed to the template, composed symbols also get their parts
+    /// with the Fachzeichen as data url.
+    pub(crate) fn context(&self, tera: &Tera) -> Result<BTreeMap<String, String>, Error> {
+        let mut context = self.base_context();
+        if let Some(composed) = &self.composed {
+            let fachzeichen = match &composed.fachzeichen {
+                None => None,
+                Some(Fachzeichen::File(path)) => Some(file_to_base64(path).map_err(|e| Error::io(path, e))?),
+                Some(Fachzeichen::Template(template)) => Some(str_to_base64(&self.render(tera, template, &context)?)),
+            };
+            context.insert("rahmen".to_string(), composed.rahmen.clone());
+            context.insert(
+                "fachzeichen".to_string(),
+                fachzeichen
+                    .map(|data| format!("data:image/svg+xml;base64,{}", data))
+                    .unwrap_or_default(),
+            );
+        }
+        Ok(context)
+    }
+
+    fn base_context(&self) -> BTreeMap<String, String> {
+        let organisation = &self.organisation;
+        let (main_color, secondary_color) = if self.inverted {
+            (&organisation.secondary_color, &organisation.main_color)
+        } else {
+            (&organisation.main_color, &organisation.secondary_color)
+        };
+        [
+            ("value", &self.value),
+            ("organisation", &organisation.label()),
+            ("ort", &self.ort),
+            ("groesse", &self.groesse),
+            ("volunteer", &self.volunteer),
+            ("special", &self.special),
+            ("main_color", main_color),
+            ("secondary_color", secondary_color),
+            ("desc", &self.description()),
+        ]
+            .into_iter()
+            .chain(self.modifiers.fields())
+            .map(|(key, value)| (key.to_string(), value.clone()))
+            .collect()
+    }
+
+    /// Renders the resolved template with `context`.
+    pub(crate) fn render(
+        &self,
+        tera: &Tera,
+        template: &str,
+        context: &BTreeMap<String, String>,
+    ) -> Result<String, Error> {
+        Ok(tera.render(template, &Context::from_serialize(context)?)?)
+    }
+
+    /// Fails if the template doesn't draw the status of this job, the output would only be a mislabelled copy
+    /// of the symbol. `drawn` keeps the answer per template and status, so it's found out once per template.
+    pub(crate) fn check_status(
+        &self,
+        tera: &Tera,
+        template: &str,
+        context: &BTreeMap<String, String>,
+        drawn: &mut HashMap<(String, String), bool>,
+    ) -> Result<(), Error> {
+        let status = &self.modifiers.status;
+        if status.is_empty() {
+            return Ok(());
+        }
+        let key = (template.to_string(), status.clone());
+        let draws = match drawn.get(&key) {
+            Some(draws) => *draws,
+            None => {
+                let draws = draws_status(tera, template, &Context::from_serialize(context)?, status)?;
+                drawn.insert(key, draws);
+                draws
+            }
+        };
+        if !draws {
+            return Err(Error::Invalid(format!("template {} doesn't draw status {}", template, status)));
+        }
+        Ok(())
+    }
+
+    /// Renders the symbol with the `<title>`, `<desc>` and `<metadata>` of this job instead of the template's.
+    pub(crate) fn render_symbol(
+        &self,
+        tera: &Tera,
+        template: &str,
+        context: &BTreeMap<String, String>,
+        template_hash: Option<&str>,
+    ) -> Result<String, Error> {
+        let svg = self.render(tera, template, context)?;
+        // composed symbols are named by their entry, every other template by its own title
+        let name = match &self.composed {
+            Some(_) => None,
+            None => root_title(&parse_xml(&svg)?),
+        };
+        set_description(
+            &svg,
+            &self.title(name.as_deref().unwrap_or(&self.template)),
+            &self.description(),
+            &serde_json::to_string(&self.metadata(template_hash))?,
+        )
+    }
+
+    /// Human readable name of the symbol, made of the same parts as its file name with `name` for the template.
+    pub(crate) fn title(
+        &self,
+        name: &str,
+    ) -> String {
+        let groesse = uppercase_first_letter(&self.groesse);
+        [
+            self.organisation.name.as_str(),
+            name,
+            &groesse,
+            &self.value,
+            &self.special,
+            &self.ort,
+            &self.volunteer,
+            &self.modifiers.status,
+        ]
+            .iter()
+            .filter(|part| !part.is_empty())
+            .copied()
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Every part of the symbol spelled out, followed by the keywords of its catalogue entry.
+    pub(crate) fn description(&self) -> String {
+        let keywords = self.keywords.join(", ");
+        describe(&[
+            ("organisation", self.organisation.display()),
+            ("category", &self.dir),
+            ("template", &self.template),
+            ("groesse", &self.groesse),
+            ("value", &self.value),
+            ("special", &self.special),
+            ("ort", &self.ort),
+            ("volunteer", &self.volunteer),
+            ("status", &self.modifiers.status),
+            ("keywords", &keywords),
+        ])
+    }
+
+    pub(crate) fn metadata(&self, template_hash: Option<&str>) -> Metadata {
+        Metadata {
+            organisation: self.organisation.name.clone(),
+            category: self.dir.clone(),
+            template: self.template.clone(),
+            value: self.value.clone(),
+            special: self.special.clone(),
+            ort: self.ort.clone(),
+            groesse: self.groesse.clone(),
+            volunteer: self.volunteer.clone(),
+            status: self.modifiers.status.clone(),
+            keywords: self.keywords.clone(),
+            inverted: self.inverted,
+            generator: Metadata::generator(),
+            template_hash: template_hash.map(str::to_string),
+        }
+    }
+}
+
+/// What an output shows, embedded as json into its `<metadata>` so it can be indexed without parsing its path.
+#[derive(Debug, Clone, Default, Serialize)]
+pub(crate) struct Metadata {
+    pub(crate) organisation: String,
+    /// Directory of the template, e.g. `einheit`, or of a static file below its organisation
+    pub(crate) category: String,
+    pub(crate) template: String,
+    pub(crate) value: String,
+    pub(crate) special: String,
+    pub(crate) ort: String,
+    pub(crate) groesse: String,
+    pub(crate) volunteer: String,
+    pub(crate) status: String,
+    pub(crate) keywords: Vec<String>,
+    pub(crate) inverted: bool,
+    pub(crate) generator: String,
+    /// Hash over the template and every template it includes, extends or imports, or over the static file
+    pub(crate) template_hash: Option<String>,
+}
+
+impl Metadata {
+    fn generator() -> String {
+        format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))
+    }
+}
+
+/// A static svg file copied into the output.
+#[derive(Debug, Clone)]
+pub(crate) struct StaticJob {
+    pub(crate) target: PathBuf,
+    /// Path of the file below the static directory, e.g. `THW/FK/Fernmeldewesen/Antenne.svg`
+    pub(crate) path: PathBuf,
+    pub(crate) source: Source,
+}
+
+impl StaticJob {
+    /// The file name without extension, e.g. `Kabel LWL`.
+    pub(crate) fn title(&self) -> String {
+        self.path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().replace('_', " "))
+            .unwrap_or_default()
+    }
+
+    /// The organisation is the first directory below the static directory, the others are the category.
+    pub(crate) fn description(&self) -> String {
+        let (organisation, category) = self.organisation_and_category();
+        describe(&[
+            ("organisation", &organisation),
+            ("category", &category),
+            ("template", &self.title()),
+        ])
+    }
+
+    /// `content` is the static file, its hash stands in for the template hash.
+    pub(crate) fn metadata(&self, content: &str) -> Metadata {
+        let (organisation, category) = self.organisation_and_category();
+        Metadata {
+            organisation,
+            category,
+            template: self.path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            generator: Metadata::generator(),
+            template_hash: Some(hash_str(content)),
+            ..Metadata::default()
+        }
+    }
+
+    fn organisation_and_category(&self) -> (String, String) {
+        let directories: Vec<String> = self.path
+            .parent()
+            .map(|parent| parent.iter().map(|name| name.to_string_lossy().into_owned()).collect())
+            .unwrap_or_default();
+        match directories.split_first() {
+            Some((organisation, category)) => (organisation.clone(), category.join("/")),
+            None => (String::new(), String::new()),
+        }
+    }
+}
+
+/// The non-empty fields as `key: value` pairs.
+fn describe(fields: &[(&str, &str)]) -> String {
+    fields
+        .iter()
+        .filter(|(_, value)| !value.is_empty())
+        .map(|(key, value)| format!("{}: {}", key, value))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// Every file a run produces, computed before anything is written.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Plan {
+    pub(crate) symbols: Vec<(Organisation, Vec<SymbolJob>)>,
+    pub(crate) volunteers: Vec<SymbolJob>,
+    pub(crate) statics: Vec<StaticJob>,
+}
+
+/// Two outputs with the same path, or paths only differing in case.
+#[derive(Debug, Clone)]
+pub(crate) struct Collision {
+    pub(crate) target: PathBuf,
+    pub(crate) source: Source,
+    pub(crate) other_target: PathBuf,
+    pub(crate) other_source: Source,
+}
+
+impl Collision {
+    pub(crate) fn case_only(&self) -> bool {
+        self.target != self.other_target
+    }
+
+    pub(crate) fn message(&self) -> String {
+        if self.case_only() {
+            format!(
+                "{} only differs in case from {} written by {}",
+                self.target.display(),
+                self.other_target.display(),
+                self.other_source
+            )
+        } else {
+            format!(
+                "{} is also written by {}",
+                self.target.display(),
+                self.other_source
+            )
+        }
+    }
+}
+
+impl Plan {
+    pub(crate) fn new(
+        output: &Path,
+        static_dir: &Path,
+        cfg: &Config,
+        volunteer_config: &VolunteerConfig,
+    ) -> Plan {
+        let symbols = cfg.catalogue()
+            .into_iter()
+            .map(|(organisation, entries)| {
+                let jobs = plan_symbols(output, static_dir, &organisation, &entries);
+                (organisation, with_status_variants(output, jobs, &cfg.status_variants))
+            })
+            .collect();
+        let volunteers = if volunteer_config.enabled {
+            let jobs = plan_volunteers(
+                output,
+                cfg,
+                volunteer_config.personen.as_deref().unwrap_or_default(),
+            );
+            with_status_variants(output, jobs, &cfg.status_variants)
+        } else {
+            Vec::new()
+        };
+
+        Plan {
+            symbols,
+            volunteers,
+            statics: plan_static(static_dir, output),
+        }
+    }
+
+    pub(crate) fn jobs(&self) -> impl Iterator<Item = &SymbolJob> {
+        self.symbols
+            .iter()
+            .flat_map(|(_, jobs)| jobs.iter())
+            .chain(self.volunteers.iter())
+    }
+
+    fn outputs(&self) -> impl Iterator<Item = (&Path, &Source)> {
+        self.jobs()
+            .map(|job| (job.target.as_path(), &job.source))
+            .chain(self.statics.iter().map(|job| (job.target.as_path(), &job.source)))
+    }
+
+    /// Outputs written more than once, including ones colliding on case-insensitive file systems.
+    pub(crate) fn collisions(&self) -> Vec<Collision> {
+        let mut writers: HashMap<String, (&Path, &Source)> = HashMap::new();
+        let mut collisions = Vec::new();
+        for (target, source) in self.outputs() {
+            let key = target.to_string_lossy().to_lowercase();
+            match writers.get(&key) {
+                Some((other_target, other_source)) => collisions.push(Collision {
+                    target: target.to_path_buf(),
+                    source: source.clone(),
+                    other_target: other_target.to_path_buf(),
+                    other_source: (*other_source).clone(),
+                }),
+                None => {
+                    writers.insert(key, (target, source));
+                }
+            }
+        }
+        collisions
+    }
+
+    /// Keeps only the first declared writer of every output, so the result doesn't depend on the render order.
+    pub(crate) fn drop_duplicates(&mut self) {
+        let mut seen: HashSet<PathBuf> = HashSet::new();
+        for (_, jobs) in self.symbols.iter_mut() {
+            jobs.retain(|job| seen.insert(job.target.clone()));
+        }
+        self.volunteers.retain(|job| seen.insert(job.target.clone()));
+        self.statics.retain(|job| seen.insert(job.target.clone()));
+    }
+}
+
+/// Adds a copy of every job per status, written below `status/<status>/`.
+fn with_status_variants(
+    output: &Path,
+    jobs: Vec<SymbolJob>,
+    statuses: &[Status],
+) -> Vec<SymbolJob> {
+    let variants: Vec<SymbolJob> = statuses
+        .iter()
+        .flat_map(|status| {
+            jobs.iter().map(move |job| {
+                let relative = job.target.strip_prefix(output).unwrap_or(&job.target);
+                SymbolJob {
+                    target: output.join("status").join(status.name()).join(relative),
+                    modifiers: Modifiers {
+                        status: status.name().to_string(),
+                        ..job.modifiers.clone()
+                    },
+                    ..job.clone()
+                }
+            })
+        })
+        .collect();
+    jobs.into_iter().chain(variants).collect()
+}
+
+pub(crate) fn plan_static(
+    static_dir: &Path,
+    output: &Path,
+) -> Vec<StaticJob> {
+    WalkDir::new(static_dir)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|entry| {
+            entry.path()
+                .extension()
+                .is_some_and(|extension| extension == "svg")
+        })
+        .map(|entry| StaticJob {
+            target: output
+                .join("original")
+                .join("svg")
+                .join(entry.path().strip_prefix(static_dir).unwrap_or(entry.path())),
+            path: entry.path().strip_prefix(static_dir).unwrap_or(entry.path()).to_path_buf(),
+            source: Source {
+                file: entry.path().to_path_buf(),
+                line: None,
+            },
+        })
+        .collect()
+}
+
+pub(crate) fn plan_symbols(
+    output: &Path,
+    static_dir: &Path,
+    organisation: &Organisation,
+    entries: &[DescriptionObjects],
+) -> Vec<SymbolJob> {
+    entries
+        .iter()
+        .flat_map(|current| {
+            current.names().into_iter().flat_map(move |name| {
+                [true, false].into_iter().flat_map(move |inverted| {
+                    let name = name.clone();
+                    current.special.variants().into_iter().flat_map(move |special| {
+                        let name = name.clone();
+                        current.groesse.variants().into_iter().flat_map(move |groesse| {
+                            let name = name.clone();
+                            let special = special.clone();
+                            current.ort.variants().into_iter().map(move |ort| {
+                                let target = format!(
+                                    "{}{}.svg",
+                                    join_paths(vec!(
+                                        if inverted { "inverted" } else { "original" },
+                                        "svg",
+                                        &organisation.name,
+                                        &current.zug,
+                                        &uppercase_first_letter(&current.dir),
+                                    )),
+                                    join_filename(vec!(
+                                        &name,
+                                        &special,
+                                        &current.template,
+                                        &uppercase_first_letter(&groesse),
+                                        &ort
+                                    )),
+                                );
+
+                                SymbolJob {
+                                    target: output.join(target),
+                                    organisation: organisation.clone(),
+                                    template: current.template.clone(),
+                                    zug: current.zug.clone(),
+                                    dir: current.dir.clone(),
+                                    inverted,
+                                    value: name.clone(),
+                                    special: special.clone(),
+                                    ort,
+                                    groesse: groesse.clone(),
+                                    volunteer: String::new(),
+                                    modifiers: current.modifiers.clone(),
+                                    keywords: current.keywords.clone(),
+                                    composed: current.compose
+                                        .as_ref()
+                                        .map(|composition| Composed::new(composition, static_dir)),
+                                    source: current.source.clone(),
+                                }
+                            })
+                        })
+                    })
+                })
+            })
+        })
+        .collect()
+}
+
+pub(crate) fn plan_volunteers(
+    output: &Path,
+    cfg: &Config,
+    volunteers: &[Person],
+) -> Vec<SymbolJob> {
+    volunteers
+        .iter()
+        .flat_map(|person| {
+            let organisation = cfg.organisation(&person.organisation);
+            person.volunteer.variants().into_iter().flat_map(move |volunteer| {
+                let organisation = organisation.clone();
+                [true, false].into_iter().flat_map(move |inverted| {
+                    let organisation = organisation.clone();
+                    let volunteer = volunteer.clone();
+                    person.value.variants().into_iter().flat_map(move |special_position| {
+                        let organisation = organisation.clone();
+                        let volunteer = volunteer.clone();
+                        person.ort.variants().into_iter().map(move |ort| {
+                            let target = format!(
+                                "custom/svg/{}/{}/{}/{}-{}-{}{}.svg",
+                                if inverted { "inverted" } else { "original" },
+                                &organisation.name,
+                                &person.zug,
+                                volunteer,
+                                person.template,
+                                special_position,
+                                if ort.is_empty() { String::new() } else { format!("-{}", ort) },
+                            );
+
+                            SymbolJob {
+                                target: output.join(target),
+                                organisation: organisation.clone(),
+                                template: person.template.clone(),
+                                zug: person.zug.clone(),
+                                dir: "personen".to_string(),
+                                inverted,
+                                value: special_position.clone(),
+                                special: String::new(),
+                                ort,
+                                groesse: String::new(),
+                                volunteer: volunteer.clone(),
+                                modifiers: person.modifiers.clone(),
+                                keywords: person.keywords.clone(),
+                                composed: None,
+                                source: person.source.clone(),
+                            }
+                        })
+                    })
+                })
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plan(targets: &[&str]) -> Plan {
+        Plan {
+            symbols: Vec::new(),
+            volunteers: Vec::new(),
+            statics: targets
+                .iter()
+                .enumerate()
+                .map(|(line, target)| StaticJob {
+                    target: PathBuf::from(target),
+                    path: PathBuf::from(target),
+                    source: Source {
+                        file: PathBuf::from("config.toml"),
+                        line: Some(line + 1),
+                    },
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn distinct_outputs_dont_collide() {
+        assert!(plan(&["build/svg/THW/A.svg", "build/svg/THW/B.svg"]).collisions().is_empty());
+    }
+
+    #[test]
+    fn same_output_collides_with_the_first_writer() {
+        let collisions = plan(&["build/svg/THW/A.svg", "build/svg/THW/B.svg", "build/svg/THW/A.svg"]).collisions();
+        assert_eq!(collisions.len(), 1);
+        assert!(!collisions[0].case_only());
+        assert_eq!(collisions[0].source.line, Some(3));
+        assert_eq!(collisions[0].other_source.line, Some(1));
+        assert_eq!(collisions[0].message(), "build/svg/THW/A.svg is also written by config.toml:1");
+    }
+
+    #[test]
+    fn outputs_only_differing_in_case_collide() {
+        let collisions = plan(&["build/svg/THW/FK/Gruppe.svg", "build/svg/thw/fk/gruppe.svg"]).collisions();
+        assert_eq!(collisions.len(), 1);
+        assert!(collisions[0].case_only());
+        assert_eq!(collisions[0].target, PathBuf::from("build/svg/thw/fk/gruppe.svg"));
+        assert_eq!(collisions[0].other_target, PathBuf::from("build/svg/THW/FK/Gruppe.svg"));
+    }
+
+    #[test]
+    fn drop_duplicates_keeps_the_first_writer() {
+        let mut plan = plan(&["build/svg/THW/A.svg", "build/svg/THW/B.svg", "build/svg/THW/A.svg"]);
+        plan.drop_duplicates();
+        let lines: Vec<_> = plan.statics.iter().map(|job| job.source.line).collect();
+        assert_eq!(lines, [Some(1), Some(2)]);
+    }
+}
@@ -0,0 +1,160 @@
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use walkdir::WalkDir;
+
+use crate::config::Raster;
+use crate::error::{Error, Failure};
+use crate::manifest::Manifest;
+use crate::plan::Plan;
+use crate::svg_tools::raster_outputs;
+use crate::utils::path_to_id;
+
+/// Top level directories of the output written by a build, nothing outside of them is touched.
+const OUTPUT_DIRS: [&str; 5] = ["original", "inverted", "custom", "status", "drawio"];
+
+/// Files in the output directory the current catalogue and static tree don't produce anymore.
+pub(crate) fn stale_outputs(
+    output: &Path,
+    plan: &Plan,
+    raster: &Raster,
+) -> Vec<PathBuf> {
+    let mut expected: HashSet<PathBuf> = HashSet::new();
+    let svgs = plan
+        .jobs()
+        .map(|job| &job.target)
+        .chain(plan.statics.iter().map(|job| &job.target));
+    for svg_path in svgs {
+        expected.extend(raster_outputs(output, svg_path, raster));
+        let id = path_to_id(output, svg_path.parent().unwrap_or(output));
+        expected.insert(output.join("drawio").join(format!("{}.xml", id)));
+        expected.insert(svg_path.clone());
+    }
+
+    OUTPUT_DIRS
+        .iter()
+        .flat_map(|dir| {
+            WalkDir::new(output.join(dir))
+                .sort_by_file_name()
+                .into_iter()
+                .filter_map(|e| e.ok())
+        })
+        .filter(|entry| entry.file_type().is_file())
+        .map(|entry| entry.into_path())
+        .filter(|path| !expected.contains(path))
+        .collect()
+}
+
+/// Removes everything a build writes into `output`, the directory itself only if nothing else is left.
+///
+/// Refuses to touch a directory without a manifest, so a mistyped `--output` can't delete unrelated files.
+pub(crate) fn clean(output: &Path) -> Result<(), Error> {
+    if !output.exists() {
+        return Ok(());
+    }
+    let manifest = Manifest::path(output);
+    if !manifest.is_file() {
+        return Err(Error::Invalid(format!(
+            "{} has no {}, refusing to clean a directory which wasn't written by a build",
+            output.display(),
+            manifest.file_name().unwrap_or_default().to_string_lossy(),
+        )));
+    }
+
+    for dir in OUTPUT_DIRS.iter().map(|dir| output.join(dir)).filter(|dir| dir.exists()) {
+        fs::remove_dir_all(&dir).map_err(|e| Error::io(&dir, e))?;
+    }
+    fs::remove_file(&manifest).map_err(|e| Error::io(&manifest, e))?;
+    if fs::read_dir(output).is_ok_and(|mut entries| entries.next().is_none()) {
+        fs::remove_dir(output).map_err(|e| Error::io(output, e))?;
+    }
+    Ok(())
+}
+
+/// Removes the stale files and the directories left empty by it.
+pub(crate) fn remove(
+    output: &Path,
+    stale: &[PathBuf],
+) -> Vec<Failure> {
+    let mut failures = Vec::new();
+    for path in stale {
+        match fs::remove_file(path) {
+            Ok(()) => println!("removed {}", path.display()),
+            Err(error) => failures.push(Failure::new(path, None, Error::io(path, error))),
+        }
+    }
+
+    // deepest directories first, so parents only containing empty directories go as well
+    let mut dirs: Vec<PathBuf> = OUTPUT_DIRS
+        .iter()
+        .flat_map(|dir| WalkDir::new(output.join(dir)).into_iter().filter_map(|e| e.ok()))
+        .filter(|entry| entry.file_type().is_dir())
+        .map(|entry| entry.into_path())
+        .collect();
+    dirs.sort_by_key(|dir| std::cmp::Reverse(dir.components().count()));
+    for dir in dirs {
+        let empty = fs::read_dir(&dir).is_ok_and(|mut entries| entries.next().is_none());
+        if empty {
+            if let Err(error) = fs::remove_dir(&dir) {
+                failures.push(Failure::new(&dir, None, Error::io(&dir, error)));
+            }
+        }
+    }
+    failures
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Source;
+    use crate::plan::StaticJob;
+
+    #[test]
+    fn only_files_the_plan_doesnt_produce_are_stale() {
+        let output = std::env::temp_dir().join(format!("taktische-zeichen-prune-{}", std::process::id()));
+        let files = [
+            "original/svg/THW/FK/A.svg",
+            "original/png/64/THW/FK/A.png",
+            "drawio/THW-FK-original.xml",
+            // removed from the catalogue
+            "original/svg/THW/FK/Old.svg",
+            "original/png/64/THW/FK/Old.png",
+            "drawio/THW-Old-original.xml",
+            // a size which isn't configured anymore
+            "original/png/128/THW/FK/A.png",
+            // not written by a build
+            "manifest.json",
+            "notes.txt",
+        ];
+        for file in files {
+            let path = output.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, "").unwrap();
+        }
+        let plan = Plan {
+            symbols: Vec::new(),
+            volunteers: Vec::new(),
+            statics: vec![StaticJob {
+                target: output.join("original/svg/THW/FK/A.svg"),
+                path: PathBuf::from("THW/FK/A.svg"),
+                source: Source::default(),
+            }],
+        };
+        let raster: Raster = toml::from_str("sizes = [64]\nformats = [\"png\"]").unwrap();
+
+        let stale = stale_outputs(&output, &plan, &raster);
+        fs::remove_dir_all(&output).unwrap();
+        assert_eq!(
+            stale,
+            [
+                "original/png/128/THW/FK/A.png",
+                "original/png/64/THW/FK/Old.png",
+                "original/svg/THW/FK/Old.svg",
+                "drawio/THW-Old-original.xml",
+            ].map(|file| output.join(file)),
+        );
+    }
+}
@@ -0,0 +1,171 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use resvg::usvg::fontdb;
+use tera::Tera;
+
+use crate::config::{self, Composition, Config, Modifiers, RasterFormat, Source};
+use crate::error::Error;
+use crate::plan::{Composed, SymbolJob};
+use crate::svg_tools::{load_fonts, svg_to_raster};
+use crate::templates::{fixes_groesse, load_templates, template_hashes, TemplateHash};
+
+/// A single symbol, described like an entry of the catalogue.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct SymbolSpec {
+    /// Name or key of an organisation declared in the catalogue, e.g. `THW`
+    pub organisation: String,
+    /// Template name without `.template.svg`, e.g. `Gruppe`
+    pub template: String,
+    /// Directory of the template, e.g. `einheit`
+    pub dir: String,
+    /// Looked up before `dir` for organisation or zug specific templates
+    pub zug: String,
+    pub value: String,
+    pub special: String,
+    pub ort: String,
+    pub volunteer: String,
+    pub inverted: bool,
+    /// Stärkeangabe, e.g. `1/2/9/12`
+    pub staerke: String,
+    pub zeit: String,
+    pub herkunft: String,
+    pub einsatzbereitschaft: String,
+    pub funkrufname: String,
+    /// `geplant` or `vermutet` draw the frame dashed, `ausgefallen` strikes it through
+    pub status: String,
+    /// Composes the symbol from this frame instead of rendering `template`, e.g. `einheit`
+    pub rahmen: String,
+    /// Unit size drawn above the frame by templates using `groesse.template.xml`, e.g. `gruppe`
+    pub groesse: String,
+    /// Fachzeichen of a composed symbol, an svg below the static directory or a `.template.svg`
+    pub fachzeichen: String,
+    /// Added to the `<desc>` of the symbol
+    pub keywords: Vec<String>,
+}
+
+/// Renders symbols with the organisations of a catalogue and a template tree, both loaded once.
+pub struct SymbolRenderer {
+    cfg: Config,
+    tera: Tera,
+    template_hashes: HashMap<String, TemplateHash>,
+    static_dir: PathBuf,
+    fontdb: OnceLock<fontdb::Database>,
+}
+
+impl SymbolRenderer {
+    /// Loads the catalogue at `config` (with its includes) and every template below `templates`,
+    /// Fachzeichen of composed symbols are looked up below `static_dir`.
+    ///
+    /// Templates which can't be parsed are left out, symbols using them fail to render.
+    pub fn new(
+        config: &Path,
+        templates: &Path,
+        static_dir: &Path,
+    ) -> Result<SymbolRenderer, Error> {
+        let cfg = config::parse_config(config)?;
+        let (tera, _) = load_templates(templates)?;
+        Ok(SymbolRenderer {
+            cfg,
+            tera,
+            template_hashes: template_hashes(templates),
+            static_dir: static_dir.to_path_buf(),
+            fontdb: OnceLock::new(),
+        })
+    }
+
+    /// Name of the declared organisation with this name or key.
+    pub fn organisation_name(&self, name: &str) -> Option<String> {
+        self.cfg
+            .find_organisation(name)
+            .map(|organisation| organisation.name.clone())
+    }
+
+    pub fn render_svg(&self, spec: &SymbolSpec) -> Result<String, Error> {
+        let job = self.job(spec)?;
+        let template = job
+            .resolve_template(&self.tera)
+            .ok_or_else(|| Error::MissingTemplate(job.template_name()))?;
+        // the drawing would show the template's size while title and metadata name this one
+        if !job.groesse.is_empty() && job.composed.is_none() && fixes_groesse(&self.tera, &template) {
+            return Err(Error::Invalid(format!(
+                "groesse {:?}: template {} draws its own size, use einheit/Einheit instead",
+                job.groesse,
+                job.template,
+            )));
+        }
+        let template_hash = self.template_hashes.get(&template).map(|hash| hash.hash.as_str());
+        let context = job.context(&self.tera)?;
+        job.check_status(&self.tera, &template, &context, &mut HashMap::new())?;
+        job.render_symbol(&self.tera, &template, &context, template_hash)
+    }
+
+    /// Renders a symbol into a raster of `size` x `size` pixels, encoded as `format`.
+    pub fn render_raster(
+        &self,
+        spec: &SymbolSpec,
+        size: u32,
+        format: RasterFormat,
+    ) -> Result<Vec<u8>, Error> {
+        let svg = self.render_svg(spec)?;
+        self.svg_to_raster(&svg, size, format)
+    }
+
+    /// Converts any svg into a raster using the `[raster]` settings of the catalogue, like the build does.
+    ///
+    /// The installed fonts are loaded on the first call.
+    pub fn svg_to_raster(
+        &self,
+        svg: &str,
+        size: u32,
+        format: RasterFormat,
+    ) -> Result<Vec<u8>, Error> {
+        let settings = self.cfg.raster.resolve("");
+        let fontdb = self.fontdb.get_or_init(load_fonts);
+        svg_to_raster(svg.as_bytes(), size, format, &settings, fontdb)
+    }
+
+    fn job(&self, spec: &SymbolSpec) -> Result<SymbolJob, Error> {
+        let organisation = self.cfg
+            .find_organisation(&spec.organisation)
+            .cloned()
+            .ok_or_else(|| Error::Invalid(format!("{:?} is not a declared organisation", spec.organisation)))?;
+        let modifiers = Modifiers {
+            staerke: spec.staerke.clone(),
+            zeit: spec.zeit.clone(),
+            herkunft: spec.herkunft.clone(),
+            einsatzbereitschaft: spec.einsatzbereitschaft.clone(),
+            funkrufname: spec.funkrufname.clone(),
+            status: spec.status.clone(),
+        };
+        if let Some(problem) = modifiers.problems().into_iter().next() {
+            return Err(Error::Invalid(problem));
+        }
+        Ok(SymbolJob {
+            target: PathBuf::new(),
+            organisation,
+            template: spec.template.clone(),
+            zug: spec.zug.clone(),
+            dir: spec.dir.clone(),
+            inverted: spec.inverted,
+            value: spec.value.clone(),
+            special: spec.special.clone(),
+            ort: spec.ort.clone(),
+            groesse: spec.groesse.clone(),
+            volunteer: spec.volunteer.clone(),
+            modifiers,
+            keywords: spec.keywords.clone(),
+            composed: (!spec.rahmen.is_empty()).then(|| Composed::new(
+                &Composition {
+                    rahmen: spec.rahmen.clone(),
+                    fachzeichen: spec.fachzeichen.clone(),
+                },
+                &self.static_dir,
+            )),
+            source: Source::default(),
+        })
+    }
+}
@@ -0,0 +1,612 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    io,
+    ops::RangeInclusive,
+    path::{Component, Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::Serialize;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::Paths;
+use crate::config::{RasterFormat, GROESSEN, RAHMEN};
+use crate::error::Error;
+use crate::manifest::Manifest;
+use crate::renderer::{SymbolRenderer, SymbolSpec};
+use crate::svg_tools::{parse_xml, raster_path, root_title};
+use crate::utils::{hash_str, map_entries, path_to_id, path_to_title};
+
+const GALLERY: &str = include_str!("gallery.html");
+/// Rendered symbols kept in memory before the cache is dropped
+const CACHE_SIZE: usize = 1024;
+const MAX_LABEL: usize = 64;
+const DEFAULT_SIZE: u32 = 256;
+const SIZES: RangeInclusive<u32> = 16..=2048;
+
+#[derive(Debug, Serialize)]
+struct Group {
+    id: String,
+    symbols: Vec<Symbol>,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct Symbol {
+    title: String,
+    /// The `<title>` of the svg, spelled out where the file name transliterates
+    name: String,
+    original: Option<Variant>,
+    inverted: Option<Variant>,
+}
+
+#[derive(Debug, Serialize)]
+struct Variant {
+    svg: String,
+    /// Modification time, changes the image urls whenever a symbol is rebuilt
+    version: u64,
+    downloads: Vec<Download>,
+}
+
+#[derive(Debug, Serialize)]
+struct Download {
+    label: String,
+    path: String,
+}
+
+/// The gallery index as served, only rebuilt after a build changed the manifest.
+#[derive(Debug, Default)]
+struct IndexCache {
+    /// Modification time of the manifest the index was built for, `None` before the first request
+    manifest: Option<Option<SystemTime>>,
+    body: String,
+    etag: String,
+}
+
+impl IndexCache {
+    fn get(&mut self, output: &Path) -> (&str, &str) {
+        let modified = manifest_modified(output);
+        if self.manifest != Some(modified) {
+            self.body = serde_json::to_string(&index(output)).unwrap_or_else(|_| "[]".to_string());
+            self.etag = format!("\"{}\"", hash_str(&self.body));
+            self.manifest = Some(modified);
+        }
+        (&self.body, &self.etag)
+    }
+}
+
+/// Written at the end of every build, so templates and catalogue may have changed whenever it does.
+fn manifest_modified(output: &Path) -> Option<SystemTime> {
+    fs::metadata(Manifest::path(output))
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// Renders symbols which aren't part of the catalogue, see [`OnDemand::render`].
+struct OnDemand {
+    renderer: SymbolRenderer,
+    cache: HashMap<String, Vec<u8>>,
+}
+
+/// A rejected render request with its http status.
+type Rejection = (u16, String);
+
+/// Serves a gallery of the output directory and renders symbols on demand until the process is stopped.
+pub(crate) fn serve(
+    address: &str,
+    paths: &Paths,
+) -> Result<(), Error> {
+    let server = Server::http(address).map_err(|source| Error::Serve {
+        address: address.to_string(),
+        source,
+    })?;
+    let output = &paths.output;
+    let mut manifest = manifest_modified(output);
+    let mut on_demand = OnDemand::load_or_warn(paths);
+    println!("serving {} on http://{}/", output.display(), server.server_addr());
+
+    let mut index = IndexCache::default();
+    for request in server.incoming_requests() {
+        // picks up the templates and catalogue a build (e.g. of a running `watch`) used, along with dropping the cache
+        let modified = manifest_modified(output);
+        if modified != manifest {
+            manifest = modified;
+            on_demand = OnDemand::load_or_warn(paths);
+        }
+        let url = request.url().to_string();
+        if let Err(error) = respond(request, output, &mut index, on_demand.as_mut()) {
+            eprintln!("warning: {}: {}", url, error);
+        }
+    }
+    Ok(())
+}
+
+fn respond(
+    request: Request,
+    output: &Path,
+    index: &mut IndexCache,
+    on_demand: Option<&mut OnDemand>,
+) -> std::io::Result<()> {
+    if request.method() != &Method::Get {
+        return request.respond(Response::empty(405));
+    }
+    let (path, query) = match request.url().split_once('?') {
+        Some((path, query)) => (path.to_string(), query.to_string()),
+        None => (request.url().to_string(), String::new()),
+    };
+
+    if path == "/" {
+        return request.respond(Response::from_string(GALLERY).with_header(content_type("text/html; charset=utf-8")));
+    }
+    // polled by every open gallery, the output tree is only walked again after a build
+    if path == "/symbols.json" {
+        let (body, etag) = index.get(output);
+        let etag_header = Header::from_bytes("ETag", etag).expect("valid header");
+        let unchanged = request
+            .headers()
+            .iter()
+            .any(|header| header.field.equiv("If-None-Match") && header.value.as_str() == etag);
+        if unchanged {
+            return request.respond(Response::empty(304).with_header(etag_header).with_header(no_cache()));
+        }
+        let body = body.to_string();
+        return request.respond(
+            Response::from_string(body)
+                .with_header(content_type("application/json"))
+                .with_header(etag_header)
+                .with_header(no_cache()),
+        );
+    }
+    if let Some(symbol) = path.strip_prefix("/render/") {
+        let result = match on_demand {
+            Some(on_demand) => on_demand.render(symbol, &query),
+            None => Err((503, "rendering on demand is disabled".to_string())),
+        };
+        return match result {
+            Ok((content, mime_type, cached)) => request.respond(
+                Response::from_data(content)
+                    .with_header(content_type(mime_type))
+                    .with_header(Header::from_bytes("X-Cache", if cached { "hit" } else { "miss" }).expect("valid header")),
+            ),
+            Err((status, message)) => request.respond(Response::from_string(message).with_status_code(status)),
+        };
+    }
+    if let Some(file) = path.strip_prefix("/files/").and_then(|file| output_file(output, file)) {
+        if let Ok(content) = fs::read(&file) {
+            return request.respond(
+                Response::from_data(content)
+                    .with_header(content_type(mime_type(&file)))
+                    .with_header(no_cache()),
+            );
+        }
+    }
+    request.respond(Response::from_string("not found").with_status_code(404))
+}
+
+impl OnDemand {
+    fn load_or_warn(paths: &Paths) -> Option<OnDemand> {
+        match OnDemand::load(paths) {
+            Ok(on_demand) => Some(on_demand),
+            Err(error) => {
+                eprintln!("warning: rendering on demand is disabled: {}", error.report());
+                None
+            }
+        }
+    }
+
+    fn load(paths: &Paths) -> Result<OnDemand, Error> {
+        Ok(OnDemand {
+            renderer: SymbolRenderer::new(&paths.config, &paths.templates, &paths.static_dir)?,
+            cache: HashMap::new(),
+        })
+    }
+
+    /// Handles `/render/{organisation}/{dir}/{template}.svg|png?value=..&special=..&ort=..&volunteer=..&zug=..&inverted=1&size=256`
+    /// the modifiers `staerke`, `zeit`, `herkunft`, `einsatzbereitschaft`, `funkrufname` and `status`,
+    /// `groesse` and `rahmen` and `fachzeichen` for a composed symbol,
+    /// `symbol` is the part after `/render/`.
+    fn render(
+        &mut self,
+        symbol: &str,
+        query: &str,
+    ) -> Result<(Vec<u8>, &'static str, bool), Rejection> {
+        let not_found = || (404, format!("unknown symbol {}", symbol));
+        let (organisation, dir, template, png) = parse_symbol(symbol)?.ok_or_else(not_found)?;
+        let extension = if png { "png" } else { "svg" };
+        let organisation = self.renderer
+            .organisation_name(&organisation)
+            .ok_or_else(|| (404, format!("unknown organisation {:?}", organisation)))?;
+        let mut parameters = parse_query(query)?;
+        let size = match parameters.get("size") {
+            None => DEFAULT_SIZE,
+            Some(size) => size
+                .parse()
+                .ok()
+                .filter(|size| SIZES.contains(size))
+                .ok_or_else(|| (400, format!("size has to be between {} and {}", SIZES.start(), SIZES.end())))?,
+        };
+        // svgs scale by themselves, the same url works for both formats
+        if !png {
+            parameters.remove("size");
+        }
+        let parameter = |key: &str| parameters.get(key).cloned().unwrap_or_default();
+        let inverted = match parameter("inverted").as_str() {
+            "" | "0" | "false" => false,
+            "1" | "true" => true,
+            other => return Err((400, format!("invalid inverted {:?}", other))),
+        };
+
+        let key = format!("{}/{}/{}.{}?{:?}", organisation, dir, template, extension, parameters);
+        let mime_type = if png { "image/png" } else { "image/svg+xml" };
+        if let Some(content) = self.cache.get(&key) {
+            return Ok((content.clone(), mime_type, true));
+        }
+
+        let spec = SymbolSpec {
+            organisation,
+            template,
+            dir,
+            zug: parameter("zug"),
+            value: parameter("value"),
+            special: parameter("special"),
+            ort: parameter("ort"),
+            volunteer: parameter("volunteer"),
+            inverted,
+            staerke: parameter("staerke"),
+            zeit: parameter("zeit"),
+            herkunft: parameter("herkunft"),
+            einsatzbereitschaft: parameter("einsatzbereitschaft"),
+            funkrufname: parameter("funkrufname"),
+            status: parameter("status"),
+            rahmen: parameter("rahmen"),
+            groesse: parameter("groesse"),
+            fachzeichen: parameter("fachzeichen"),
+            keywords: Vec::new(),
+        };
+        let content = if png {
+            self.renderer.render_raster(&spec, size, RasterFormat::Png)
+        } else {
+            self.renderer.render_svg(&spec).map(String::into_bytes)
+        };
+        let content = content.map_err(|error| match error {
+            Error::MissingTemplate(_) => not_found(),
+            Error::Invalid(message) => (400, message),
+            Error::Io { source, .. } if source.kind() == io::ErrorKind::NotFound => {
+                (404, format!("unknown fachzeichen {:?}", parameter("fachzeichen")))
+            }
+            error => (500, error.report()),
+        })?;
+
+        if self.cache.len() >= CACHE_SIZE {
+            self.cache.clear();
+        }
+        self.cache.insert(key, content.clone());
+        Ok((content, mime_type, false))
+    }
+}
+
+/// Organisation, dir, template and whether a png is requested, `None` if the path can't be a symbol.
+fn parse_symbol(symbol: &str) -> Result<Option<(String, String, String, bool)>, Rejection> {
+    let segments: Vec<String> = symbol
+        .split('/')
+        .map(percent_decode)
+        .collect::<Option<_>>()
+        .ok_or_else(|| (400, "invalid url encoding".to_string()))?;
+    let [organisation, dir, file] = segments.as_slice() else {
+        return Ok(None);
+    };
+    let Some((template, extension)) = file.rsplit_once('.') else {
+        return Ok(None);
+    };
+    let png = match extension {
+        "svg" => false,
+        "png" => true,
+        _ => return Ok(None),
+    };
+    check_name("dir", dir)?;
+    check_name("template", template)?;
+    Ok(Some((organisation.clone(), dir.clone(), template.to_string(), png)))
+}
+
+fn parse_query(query: &str) -> Result<BTreeMap<String, String>, Rejection> {
+    let mut parameters = BTreeMap::new();
+    for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let value = percent_decode(&value.replace('+', " "))
+            .ok_or_else(|| (400, "invalid url encoding".to_string()))?;
+        match key {
+            "value" | "special" | "ort" | "volunteer" | "staerke" | "zeit" | "herkunft"
+            | "einsatzbereitschaft" | "funkrufname" | "status" => check_label(key, &value)?,
+            "zug" => check_name(key, &value)?,
+            // the templates throw on anything else, which would be reported as an internal error
+            "rahmen" => check_known(key, &value, &RAHMEN)?,
+            "groesse" => check_known(key, &value, &GROESSEN)?,
+            "fachzeichen" => check_path(key, &value)?,
+            "inverted" | "size" => {}
+            _ => return Err((400, format!("unknown parameter {:?}", key))),
+        }
+        parameters.insert(key.to_string(), value);
+    }
+    Ok(parameters)
+}
+
+/// Dirs, templates and zuege are plain names, so they can't point anywhere else in the template tree.
+fn check_name(
+    field: &str,
+    value: &str,
+) -> Result<(), Rejection> {
+    if value.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-') {
+        Ok(())
+    } else {
+        Err((400, format!("invalid {} {:?}", field, value)))
+    }
+}
+
+/// A relative path which stays inside of its directory.
+fn check_path(
+    field: &str,
+    value: &str,
+) -> Result<(), Rejection> {
+    let relative = Path::new(value).components().all(|component| matches!(component, Component::Normal(_)));
+    if relative && !value.chars().any(char::is_control) {
+        Ok(())
+    } else {
+        Err((400, format!("invalid {} {:?}", field, value)))
+    }
+}
+
+/// Empty or one of `known`.
+fn check_known(
+    field: &str,
+    value: &str,
+    known: &[&str],
+) -> Result<(), Rejection> {
+    if value.is_empty() || known.contains(&value) {
+        Ok(())
+    } else {
+        Err((400, format!("unknown {} {:?}, expected one of {}", field, value, known.join(", "))))
+    }
+}
+
+fn check_label(
+    field: &str,
+    value: &str,
+) -> Result<(), Rejection> {
+    if value.chars().count() > MAX_LABEL {
+        return Err((400, format!("{} is longer than {} characters", field, MAX_LABEL)));
+    }
+    if value.chars().any(char::is_control) {
+        return Err((400, format!("{} contains control characters", field)));
+    }
+    Ok(())
+}
+
+/// Every symbol in the output directory, grouped like the draw.io libraries.
+fn index(output: &Path) -> Vec<Group> {
+    let mut groups: BTreeMap<String, BTreeMap<String, Symbol>> = BTreeMap::new();
+    for svg_path in map_entries(output) {
+        let relative = svg_path.strip_prefix(output).unwrap_or(&svg_path);
+        let inverted = relative.components().any(|component| component.as_os_str() == "inverted");
+        let id = path_to_id(output, svg_path.parent().unwrap_or(output));
+        let id = id
+            .trim_end_matches("-original")
+            .trim_end_matches("-inverted")
+            .to_string();
+        let title = path_to_title(output, &svg_path)
+            .split(' ')
+            .filter(|word| *word != "original" && *word != "inverted")
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        let version = fs::metadata(&svg_path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |duration| duration.as_secs());
+        let variant = Variant {
+            svg: url_path(relative),
+            version,
+            downloads: downloads(output, &svg_path),
+        };
+
+        let symbol = groups
+            .entry(id)
+            .or_default()
+            .entry(title.clone())
+            .or_insert_with(|| Symbol { title, ..Symbol::default() });
+        if symbol.name.is_empty() {
+            symbol.name = svg_title(&svg_path).unwrap_or_else(|| symbol.title.clone());
+        }
+        if inverted {
+            symbol.inverted = Some(variant);
+        } else {
+            symbol.original = Some(variant);
+        }
+    }
+
+    groups
+        .into_iter()
+        .map(|(id, symbols)| Group {
+            id,
+            symbols: symbols.into_values().collect(),
+        })
+        .collect()
+}
+
+fn svg_title(path: &Path) -> Option<String> {
+    let content = fs::read_to_string(path).ok()?;
+    root_title(&parse_xml(&content).ok()?)
+}
+
+/// The svg and every raster of it found in the output directory.
+fn downloads(
+    output: &Path,
+    svg_path: &Path,
+) -> Vec<Download> {
+    let relative = svg_path.strip_prefix(output).unwrap_or(svg_path);
+    let mut downloads = vec![Download {
+        label: "svg".to_string(),
+        path: url_path(relative),
+    }];
+
+    let prefix: PathBuf = relative
+        .components()
+        .take_while(|component| component.as_os_str() != "svg")
+        .collect();
+    for format in RasterFormat::ALL {
+        let mut sizes: Vec<u32> = fs::read_dir(output.join(&prefix).join(format.dir()))
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+            .collect();
+        sizes.sort_unstable();
+        for size in sizes {
+            let path = raster_path(output, svg_path, format, size);
+            if path.is_file() {
+                downloads.push(Download {
+                    label: format!("{} {}", format.dir(), size),
+                    path: url_path(path.strip_prefix(output).unwrap_or(&path)),
+                });
+            }
+        }
+    }
+    downloads
+}
+
+fn url_path(path: &Path) -> String {
+    path.components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// The file below `output` a request points to, nothing outside of it.
+fn output_file(
+    output: &Path,
+    url_path: &str,
+) -> Option<PathBuf> {
+    let relative = PathBuf::from(percent_decode(url_path)?);
+    if relative.components().all(|component| matches!(component, Component::Normal(_))) {
+        Some(output.join(relative))
+    } else {
+        None
+    }
+}
+
+fn percent_decode(value: &str) -> Option<String> {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' => {
+                let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+                decoded.push(u8::from_str_radix(hex, 16).ok()?);
+                i += 3;
+            }
+            byte => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+fn mime_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("webp") => "image/webp",
+        Some("jpg") => "image/jpeg",
+        Some("xml") => "application/xml",
+        Some("json") => "application/json",
+        _ => "application/octet-stream",
+    }
+}
+
+fn content_type(value: &str) -> Header {
+    Header::from_bytes("Content-Type", value).expect("valid header")
+}
+
+fn no_cache() -> Header {
+    Header::from_bytes("Cache-Control", "no-cache").expect("valid header")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status<T: std::fmt::Debug>(result: Result<T, Rejection>) -> u16 {
+        result.unwrap_err().0
+    }
+
+    #[test]
+    fn percent_decoding() {
+        assert_eq!(percent_decode("Gruppe%20I+a").as_deref(), Some("Gruppe I+a"));
+        assert_eq!(percent_decode("%C3%96GA").as_deref(), Some("ÖGA"));
+        assert_eq!(percent_decode("..%2F..%2Fetc").as_deref(), Some("../../etc"));
+        assert_eq!(percent_decode("a%00b").as_deref(), Some("a\0b"));
+        assert_eq!(percent_decode("%4"), None);
+        assert_eq!(percent_decode("%zz"), None);
+        assert_eq!(percent_decode("%FF"), None);
+    }
+
+    #[test]
+    fn paths() {
+        assert!(check_path("fachzeichen", "fachzeichen/Bergung.svg").is_ok());
+        assert_eq!(status(check_path("fachzeichen", "../config.toml")), 400);
+        assert_eq!(status(check_path("fachzeichen", "/etc/passwd")), 400);
+        assert_eq!(status(check_path("fachzeichen", "a\0.svg")), 400);
+
+        let output = Path::new("build");
+        assert_eq!(output_file(output, "original/THW/a.svg"), Some(output.join("original/THW/a.svg")));
+        assert_eq!(output_file(output, "..%2Fconfig.toml"), None);
+        assert_eq!(output_file(output, "original/%2E%2E/%2E%2E/config.toml"), None);
+    }
+
+    #[test]
+    fn labels() {
+        assert!(check_label("value", "Gruppe I").is_ok());
+        assert!(check_label("value", &"Ö".repeat(MAX_LABEL)).is_ok());
+        assert_eq!(status(check_label("value", &"a".repeat(MAX_LABEL + 1))), 400);
+        assert_eq!(status(check_label("value", "a\0b")), 400);
+        assert_eq!(status(check_label("ort", "a\nb")), 400);
+    }
+
+    #[test]
+    fn symbols() {
+        assert_eq!(
+            parse_symbol("THW/einheit/Gruppe.png").unwrap(),
+            Some(("THW".to_string(), "einheit".to_string(), "Gruppe".to_string(), true)),
+        );
+        assert_eq!(parse_symbol("THW/einheit/Gruppe.svg").unwrap().map(|symbol| symbol.3), Some(false));
+        assert_eq!(parse_symbol("THW/einheit/Gruppe.gif").unwrap(), None);
+        assert_eq!(parse_symbol("THW/Gruppe.svg").unwrap(), None);
+        assert_eq!(parse_symbol("THW/einheit/sub/Gruppe.svg").unwrap(), None);
+        assert_eq!(status(parse_symbol("THW/..%2F..%2Fsecret/Gruppe.svg")), 400);
+        assert_eq!(status(parse_symbol("THW/einheit/..%2FGruppe.svg")), 400);
+        assert_eq!(status(parse_symbol("THW/einheit/Gru%00ppe.svg")), 400);
+        assert_eq!(status(parse_symbol("THW/einheit/%ZZ.svg")), 400);
+    }
+
+    #[test]
+    fn queries() {
+        let parameters = parse_query("value=Gruppe+I&ort=M%C3%BCnchen&groesse=zug&rahmen=einheit&inverted=1").unwrap();
+        assert_eq!(parameters["value"], "Gruppe I");
+        assert_eq!(parameters["ort"], "München");
+        assert_eq!(parameters["groesse"], "zug");
+        assert!(parse_query("").unwrap().is_empty());
+        assert!(parse_query("groesse=&rahmen=").is_ok());
+
+        assert_eq!(status(parse_query("colour=red")), 400);
+        assert_eq!(status(parse_query("groesse=kompanie")), 400);
+        assert_eq!(status(parse_query("rahmen=kreis")), 400);
+        assert_eq!(status(parse_query("fachzeichen=..%2F..%2Fconfig.toml")), 400);
+        assert_eq!(status(parse_query("fachzeichen=a%00.svg")), 400);
+        assert_eq!(status(parse_query("zug=..%2Fzug")), 400);
+        assert_eq!(status(parse_query("value=%00")), 400);
+        assert_eq!(status(parse_query(&format!("value={}", "a".repeat(MAX_LABEL + 1)))), 400);
+        assert_eq!(status(parse_query("value=%E")), 400);
+    }
+}
@@ -1,10 +1,14 @@
 use std::{
-    collections::HashMap,
-    fs,
+    fs::{self, File},
+    io::{BufWriter, Write},
     path::{Path, PathBuf},
-    string::String
 };
 
+use image::{
+    codecs::{jpeg::JpegEncoder, qoi::QoiEncoder, webp::WebPEncoder},
+    ColorType,
+    ImageEncoder,
+};
 use indicatif::ProgressBar;
 use rayon::{
     iter::IntoParallelIterator,
@@ -25,102 +29,530 @@ use resvg::{
 use tiny_skia::{Pixmap, Transform};
 use usvg::Options;
 
-use crate::utils::{calc_hash, map_entries};
+use crate::config::{Raster, RasterFormat, ResolvedRaster};
+use crate::error::{Error, Failure};
+use crate::manifest::{Manifest, RasterEntry};
+use crate::utils::hash_str;
 
+/// Writes the rasters of every svg in the manifest which are missing or outdated.
 pub(crate) fn convert_svg(
     progress_bar: ProgressBar,
-    hashes: HashMap<String, String>,
-) {
-    let paths: Vec<(String, String, &i32)> = map_entries("build")
-        .iter()
-        .map(|entry: &PathBuf| {
-            let old_checksum = match hashes
-                .get(entry.to_str().unwrap()) {
-                Some(x) => x,
-                None => "CREATE",
-            };
-            let new_checksum = calc_hash(entry.to_str().unwrap());
-
-            return if old_checksum != new_checksum {
-                let svg_path = entry
-                    .to_str()
-                    .unwrap()
-                    .to_string();
-                let original_png_path = format!(
-                    "{}.png",
-                    entry
-                        .with_extension("")
-                        .to_str()
-                        .unwrap()
-                );
-
-                let paths_different_sizes: Vec<(String, String, &i32)> = [128, 256, 512, 1024, 2048]
-                    .iter()
-                    .map(|size| {
-                        let path = original_png_path.replace(
-                            "svg/",
-                            &*format!("png/{}/", size.to_string().as_str()),
-                        );
-                        (svg_path.clone(), path, size)
-                    })
-                    .collect();
-                paths_different_sizes
-            } else {
-                Vec::new()
+    output: &Path,
+    raster: &Raster,
+    manifest: &mut Manifest,
+) -> Vec<Failure> {
+    // loading the system fonts takes far longer than rendering a symbol, so it's done once per run
+    let fontdb = load_fonts();
+    let fonts = font_fingerprint(&fontdb);
+
+    let jobs: Vec<(PathBuf, PathBuf, RasterEntry)> = manifest.outputs
+        .iter_mut()
+        .filter_map(|(path, entry)| {
+            let svg_path = output.join(path);
+            let wanted = RasterEntry {
+                fonts: fonts.clone(),
+                settings: raster.resolve(&raster_key(output, &svg_path)),
             };
+            if entry.raster.as_ref() == Some(&wanted) && rasters_exist(output, &svg_path, &wanted.settings) {
+                return None;
+            }
+            entry.raster = None;
+            Some((path.clone(), svg_path, wanted))
         })
-        .flatten()
         .collect();
 
+    let results: Vec<(PathBuf, RasterEntry, Vec<Failure>)> = jobs
+        .into_par_iter()
+        .map(|(path, svg_path, wanted)| {
+            let pb = progress_bar.clone();
+            let failures = convert_svg_to_raster(output, &svg_path, &wanted.settings, &fontdb);
 
-    paths. into_par_iter().for_each(|(svg_path, png_path, size)| {
-        let pb = progress_bar.clone();
-        convert_svg_to_png(
-            &svg_path,
-            &png_path,
-            *size as f32,
-        );
+            pb.inc(1);
+            pb.set_message(format!("Converted: {}", svg_path.display()));
+            (path, wanted, failures)
+        })
+        .collect();
 
-        pb.inc(1);
-        pb.set_message(format!("Converted: {} -> {}", svg_path, png_path));
-    });
+    let mut failures = Vec::new();
+    for (path, wanted, errors) in results {
+        if errors.is_empty() {
+            if let Some(entry) = manifest.outputs.get_mut(&path) {
+                entry.raster = Some(wanted);
+            }
+        }
+        failures.extend(errors);
+    }
 
     progress_bar.finish_with_message("finished");
+    failures
 }
 
-fn convert_svg_to_png(
-    svg_path: &str,
-    png_path: &str,
-    size: f32,
-) {
-    fs::create_dir_all(Path::new(png_path).parent().unwrap()).expect("Couldn't create directory");
-    let mut opt = Options::default();
-    opt.text_rendering = TextRendering::GeometricPrecision;
+/// Every raster the configuration produces for a svg.
+pub(crate) fn raster_outputs(
+    output: &Path,
+    svg_path: &Path,
+    raster: &Raster,
+) -> Vec<PathBuf> {
+    let settings = raster.resolve(&raster_key(output, svg_path));
+    settings.formats
+        .iter()
+        .flat_map(|format| {
+            settings.sizes
+                .iter()
+                .map(|size| raster_path(output, svg_path, *format, *size))
+        })
+        .collect()
+}
 
-    opt.default_size = Size::from_wh(size, size).unwrap();
+fn rasters_exist(
+    output: &Path,
+    svg_path: &Path,
+    settings: &ResolvedRaster,
+) -> bool {
+    settings.formats.iter().all(|format| {
+        settings.sizes
+            .iter()
+            .all(|size| raster_path(output, svg_path, *format, *size).is_file())
+    })
+}
 
+/// Path of a symbol below `svg/` without the original/inverted split of the volunteers, e.g. `THW/FK/Einheit/K-Gruppe.svg`.
+fn raster_key(
+    output: &Path,
+    svg_path: &Path,
+) -> String {
+    let components: Vec<String> = svg_path
+        .strip_prefix(output)
+        .unwrap_or(svg_path)
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy().to_string())
+        .skip_while(|component| component != "svg")
+        .skip(1)
+        .collect();
+    let components = match components.first().map(String::as_str) {
+        Some("original") | Some("inverted") => &components[1..],
+        _ => &components[..],
+    };
+    components.join("/")
+}
+
+/// Maps `<output>/.../svg/<rest>.svg` to `<output>/.../<format>/<size>/<rest>.<extension>`.
+pub(crate) fn raster_path(
+    output: &Path,
+    svg_path: &Path,
+    format: RasterFormat,
+    size: u32,
+) -> PathBuf {
+    let relative = svg_path.strip_prefix(output).unwrap_or(svg_path);
+    let mut path = output.to_path_buf();
+    let mut replaced = false;
+    for component in relative.components() {
+        if !replaced && component.as_os_str() == "svg" {
+            path.push(format.dir());
+            path.push(size.to_string());
+            replaced = true;
+        } else {
+            path.push(component);
+        }
+    }
+    path.with_extension(format.extension())
+}
+
+pub(crate) fn load_fonts() -> fontdb::Database {
     let mut fontdb = fontdb::Database::new();
     fontdb.load_system_fonts();
     fontdb.load_fonts_dir("./fonts/ttf");
+    fontdb
+}
 
-    let svg_data = fs::read(svg_path).unwrap();
-    let mut tree_usvg = usvg::Tree::from_data(&svg_data, &opt).unwrap();
-    tree_usvg.convert_text(&fontdb);
-    tree_usvg.size = Size::from_wh(size, size).unwrap();
+/// Changes whenever a font is installed, removed or replaced.
+fn font_fingerprint(fontdb: &fontdb::Database) -> String {
+    let mut faces: Vec<String> = fontdb
+        .faces()
+        .map(|face| {
+            let source = match &face.source {
+                fontdb::Source::File(path) | fontdb::Source::SharedFile(path, _) => {
+                    let modified = fs::metadata(path)
+                        .and_then(|metadata| metadata.modified())
+                        .ok();
+                    format!("{}:{:?}", path.display(), modified)
+                }
+                fontdb::Source::Binary(_) => "binary".to_string(),
+            };
+            format!("{}:{}:{}", source, face.index, face.post_script_name)
+        })
+        .collect();
+    faces.sort();
+    hash_str(&faces.join("\n"))
+}
+
+/// Parses the svg once and writes every configured size and format of it.
+fn convert_svg_to_raster(
+    output: &Path,
+    svg_path: &Path,
+    settings: &ResolvedRaster,
+    fontdb: &fontdb::Database,
+) -> Vec<Failure> {
+    let mut tree = match parse_svg(svg_path, fontdb) {
+        Ok(tree) => tree,
+        Err(error) => return vec![Failure::new(svg_path, None, error)],
+    };
 
-    let tree = Tree::from_usvg(&tree_usvg);
+    let mut failures = Vec::new();
+    for size in settings.sizes.iter() {
+        let pixmap = match render(&mut tree, *size as f32) {
+            Ok(pixmap) => pixmap,
+            Err(error) => {
+                failures.push(Failure::new(svg_path, None, error));
+                continue;
+            }
+        };
+        for format in settings.formats.iter() {
+            let raster_path = raster_path(output, svg_path, *format, *size);
+            if let Err(error) = save_raster(&pixmap, &raster_path, *format, settings) {
+                failures.push(Failure::new(&raster_path, None, error));
+            }
+        }
+    }
+    failures
+}
+
+fn parse_svg(
+    svg_path: &Path,
+    fontdb: &fontdb::Database,
+) -> Result<Tree, Error> {
+    let svg_data = fs::read(svg_path).map_err(|e| Error::io(svg_path, e))?;
+    parse_svg_data(&svg_data, fontdb)
+}
+
+fn parse_svg_data(
+    svg_data: &[u8],
+    fontdb: &fontdb::Database,
+) -> Result<Tree, Error> {
+    let opt = Options {
+        text_rendering: TextRendering::GeometricPrecision,
+        ..Options::default()
+    };
+
+    let mut tree_usvg = usvg::Tree::from_data(svg_data, &opt)?;
+    tree_usvg.convert_text(fontdb);
+    Ok(Tree::from_usvg(&tree_usvg))
+}
+
+/// Renders a svg held in memory into a raster of `size` x `size` pixels.
+pub(crate) fn svg_to_raster(
+    svg_data: &[u8],
+    size: u32,
+    format: RasterFormat,
+    settings: &ResolvedRaster,
+    fontdb: &fontdb::Database,
+) -> Result<Vec<u8>, Error> {
+    let mut tree = parse_svg_data(svg_data, fontdb)?;
+    let pixmap = render(&mut tree, size as f32)?;
+    let mut data = Vec::new();
+    encode_raster(&pixmap, &mut data, format, settings)?;
+    Ok(data)
+}
+
+/// Renders the tree into a square pixmap, the view box is scaled to fit.
+fn render(
+    tree: &mut Tree,
+    size: f32,
+) -> Result<Pixmap, Error> {
+    tree.size = Size::from_wh(size, size)
+        .ok_or_else(|| Error::Raster(format!("invalid size {}", size)))?;
 
     let pixmap_size = tree.size.to_int_size();
     let mut pixmap = Pixmap::new(
         pixmap_size.width(),
         pixmap_size.height(),
-    ).unwrap();
+    ).ok_or_else(|| Error::Raster(format!("couldn't allocate {:?} pixmap", pixmap_size)))?;
 
     Tree::render(
-        &tree,
+        tree,
         Transform::default(),
         &mut pixmap.as_mut(),
     );
+    Ok(pixmap)
+}
+
+fn save_raster(
+    pixmap: &Pixmap,
+    path: &Path,
+    format: RasterFormat,
+    settings: &ResolvedRaster,
+) -> Result<(), Error> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| Error::io(parent, e))?;
+    }
+    let file = File::create(path).map_err(|e| Error::io(path, e))?;
+    let mut writer = BufWriter::new(file);
+    encode_raster(pixmap, &mut writer, format, settings)?;
+    writer.flush().map_err(|e| Error::io(path, e))
+}
+
+fn encode_raster<W: Write>(
+    pixmap: &Pixmap,
+    mut writer: W,
+    format: RasterFormat,
+    settings: &ResolvedRaster,
+) -> Result<(), Error> {
+    if format == RasterFormat::Png {
+        let png = pixmap
+            .encode_png()
+            .map_err(|e| Error::Raster(e.to_string()))?;
+        return writer
+            .write_all(&png)
+            .map_err(|e| Error::Raster(e.to_string()));
+    }
+
+    let (width, height) = (pixmap.width(), pixmap.height());
+    let rgba: Vec<u8> = pixmap
+        .pixels()
+        .iter()
+        .flat_map(|pixel| {
+            let color = pixel.demultiply();
+            [color.red(), color.green(), color.blue(), color.alpha()]
+        })
+        .collect();
+
+    match format {
+        RasterFormat::Webp => WebPEncoder::new_lossless(writer)
+            .encode(&rgba, width, height, ColorType::Rgba8)?,
+        RasterFormat::Qoi => QoiEncoder::new(writer)
+            .write_image(&rgba, width, height, ColorType::Rgba8)?,
+        RasterFormat::Jpeg => {
+            let background = parse_color(&settings.background)?;
+            let rgb: Vec<u8> = rgba
+                .chunks_exact(4)
+                .flat_map(|pixel| {
+                    let alpha = pixel[3] as u32;
+                    [0, 1, 2].map(|channel| {
+                        ((pixel[channel] as u32 * alpha + background[channel] as u32 * (255 - alpha)) / 255) as u8
+                    })
+                })
+                .collect();
+            JpegEncoder::new_with_quality(writer, settings.jpeg_quality)
+                .encode(&rgb, width, height, ColorType::Rgb8)?
+        }
+        RasterFormat::Png => unreachable!(),
+    }
+    Ok(())
+}
+
+/// Parses `#rgb` and `#rrggbb` colours.
+fn parse_color(color: &str) -> Result<[u8; 3], Error> {
+    let invalid = || Error::Invalid(format!("invalid colour {:?}", color));
+    let hex = color
+        .strip_prefix('#')
+        .filter(|hex| hex.chars().all(|c| c.is_ascii_hexdigit()))
+        .ok_or_else(invalid)?;
+    let digits: Vec<u8> = match hex.len() {
+        3 => hex.chars().map(|c| c.to_string().repeat(2)).map(|c| u8::from_str_radix(&c, 16)).collect::<Result<_, _>>(),
+        6 => (0..3).map(|i| u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16)).collect::<Result<_, _>>(),
+        _ => return Err(invalid()),
+    }.map_err(|_| invalid())?;
+    Ok([digits[0], digits[1], digits[2]])
+}
+
+/// Parses a svg as plain xml, for changes and checks usvg would normalise away.
+pub(crate) fn parse_xml(svg: &str) -> Result<roxmltree::Document<'_>, Error> {
+    // the templates declare the svg doctype, usvg accepts it as well
+    let options = roxmltree::ParsingOptions { allow_dtd: true, ..Default::default() };
+    roxmltree::Document::parse_with_options(svg, options)
+        .map_err(|e| Error::Invalid(format!("invalid xml: {}", e)))
+}
+
+/// The non-empty `<title>` of the root element.
+pub(crate) fn root_title(document: &roxmltree::Document) -> Option<String> {
+    document
+        .root_element()
+        .children()
+        .find(|node| node.has_tag_name("title"))
+        .and_then(|node| node.text())
+        .map(str::trim)
+        .filter(|title| !title.is_empty())
+        .map(str::to_string)
+}
+
+/// Replaces the `<title>`, `<desc>` and `<metadata>` of the root element, keeping everything else byte for byte.
+pub(crate) fn set_description(
+    svg: &str,
+    title: &str,
+    description: &str,
+    metadata: &str,
+) -> Result<String, Error> {
+    let document = parse_xml(svg)?;
+    let root = document.root_element();
+    let start = root
+        .first_child()
+        .map(|child| child.range().start)
+        .ok_or_else(|| Error::Invalid("empty svg".to_string()))?;
+
+    let mut result = String::with_capacity(svg.len() + metadata.len());
+    result.push_str(&svg[..start]);
+    result.push_str(&format!(
+        "\n\t<title>{}</title>\n\t<desc>{}</desc>\n\t<metadata>{}</metadata>",
+        escape_xml(title),
+        escape_xml(description),
+        escape_xml(metadata),
+    ));
+    let mut position = start;
+    let replaced = ["title", "desc", "metadata"];
+    for child in root.children().filter(|child| replaced.contains(&child.tag_name().name())) {
+        // the indentation in front of the element goes with it
+        let from = match child.prev_sibling() {
+            Some(text) if text.is_text() && text.text().unwrap_or_default().trim().is_empty() => text.range().start,
+            _ => child.range().start,
+        };
+        result.push_str(&svg[position..from.max(position)]);
+        position = child.range().end;
+    }
+    result.push_str(&svg[position..]);
+    Ok(result)
+}
+
+/// Escapes element text, quotes are left as they are so this must not be used for attribute values.
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOCTYPE: &str = r#"<!DOCTYPE svg PUBLIC "-//W3C//DTD SVG 1.1//EN" "http://www.w3.org/Graphics/SVG/1.1/DTD/svg11.dtd">"#;
+
+    fn root_text(
+        svg: &str,
+        tag: &str,
+    ) -> Option<String> {
+        let document = parse_xml(svg).unwrap();
+        let text = document
+            .root_element()
+            .children()
+            .find(|node| node.has_tag_name(tag))?
+            .text()
+            .map(str::to_string);
+        text
+    }
+
+    #[test]
+    fn replaces_existing_description() {
+        let svg = format!(
+            "{}\n<svg xmlns=\"http://www.w3.org/2000/svg\">\n\t<title>Gruppenführer</title>\n\t<desc>old</desc>\n\t<metadata>{{}}</metadata>\n\t<rect width=\"1\" height=\"1\" />\n</svg>",
+            DOCTYPE,
+        );
+        let result = set_description(&svg, "THW Gruppe", "template: Gruppe", r#"{"a":1}"#).unwrap();
+        assert_eq!(
+            result,
+            format!(
+                "{}\n<svg xmlns=\"http://www.w3.org/2000/svg\">\n\t<title>THW Gruppe</title>\n\t<desc>template: Gruppe</desc>\n\t<metadata>{{\"a\":1}}</metadata>\n\t<rect width=\"1\" height=\"1\" />\n</svg>",
+                DOCTYPE,
+            ),
+        );
+        // applying it again doesn't pile up elements
+        assert_eq!(set_description(&result, "THW Gruppe", "template: Gruppe", r#"{"a":1}"#).unwrap(), result);
+    }
+
+    #[test]
+    fn adds_missing_title() {
+        let svg = "<svg xmlns=\"http://www.w3.org/2000/svg\">\n\t<g>\n\t\t<title>inner</title>\n\t</g>\n</svg>";
+        let result = set_description(svg, "Anhänger", "", "{}").unwrap();
+        assert_eq!(
+            result,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\">\n\t<title>Anhänger</title>\n\t<desc></desc>\n\t<metadata>{}</metadata>\n\t<g>\n\t\t<title>inner</title>\n\t</g>\n</svg>",
+        );
+    }
+
+    #[test]
+    fn escapes_text() {
+        let svg = "<svg xmlns=\"http://www.w3.org/2000/svg\"><rect /></svg>";
+        let title = "Zug A & B <neu>";
+        let metadata = r#"{"value":"<&>\"'"}"#;
+        let result = set_description(svg, title, "a < b", metadata).unwrap();
+        assert!(result.contains("<title>Zug A &amp; B &lt;neu&gt;</title>"));
+        assert_eq!(root_text(&result, "title").as_deref(), Some(title));
+        assert_eq!(root_text(&result, "desc").as_deref(), Some("a < b"));
+        assert_eq!(root_text(&result, "metadata").as_deref(), Some(metadata));
+    }
+
+    #[test]
+    fn rejects_empty_svg() {
+        assert!(set_description("<svg xmlns=\"http://www.w3.org/2000/svg\"/>", "", "", "").is_err());
+        assert!(set_description("not xml", "", "", "").is_err());
+    }
+
+    #[test]
+    fn raster_path_replaces_the_svg_directory() {
+        let output = Path::new("build");
+        assert_eq!(
+            raster_path(output, Path::new("build/original/svg/THW/FK/K-Gruppe.svg"), RasterFormat::Png, 256),
+            Path::new("build/original/png/256/THW/FK/K-Gruppe.png"),
+        );
+        assert_eq!(
+            raster_path(output, Path::new("build/custom/svg/inverted/THW/svg/A.svg"), RasterFormat::Webp, 64),
+            Path::new("build/custom/webp/64/inverted/THW/svg/A.webp"),
+        );
+        // only below the output directory
+        assert_eq!(
+            raster_path(Path::new("svg"), Path::new("svg/original/svg/A.svg"), RasterFormat::Jpeg, 32),
+            Path::new("svg/original/jpeg/32/A.jpg"),
+        );
+    }
+
+    #[test]
+    fn raster_key_starts_with_the_organisation() {
+        let output = Path::new("build");
+        assert_eq!(raster_key(output, Path::new("build/original/svg/THW/FK/K-Gruppe.svg")), "THW/FK/K-Gruppe.svg");
+        assert_eq!(raster_key(output, Path::new("build/custom/svg/inverted/THW/FK/Max.svg")), "THW/FK/Max.svg");
+        assert_eq!(raster_key(output, Path::new("build/status/geplant/original/svg/FW/A.svg")), "FW/A.svg");
+    }
+
+    #[test]
+    fn parses_short_and_long_colours() {
+        assert_eq!(parse_color("#fff").unwrap(), [255, 255, 255]);
+        assert_eq!(parse_color("#1a2B3c").unwrap(), [0x1a, 0x2b, 0x3c]);
+        for invalid in ["fff", "#ffff", "#ggg", "#12345z", "#aéaaa", ""] {
+            assert!(parse_color(invalid).is_err(), "{:?}", invalid);
+        }
+    }
+
+    /// Compares rasterising every size the way `convert_svg` does with loading the fonts and parsing the svg
+    /// again for every size, run with `cargo test --release raster_timing -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn raster_timing() {
+        let svg = br##"<svg xmlns="http://www.w3.org/2000/svg" width="256" height="256" viewBox="0 0 256 256">
+            <rect x="10" y="64" width="236" height="128" fill="#003399" stroke="#ffffff" stroke-width="10" />
+            <text x="128" y="150" font-family="Roboto Slab" font-size="60" text-anchor="middle" fill="#ffffff">FK</text>
+            <text x="20" y="240" font-family="Roboto Slab" font-size="40" fill="#003399">THW</text>
+        </svg>"##;
+        let sizes = [32, 64, 128, 256, 1024];
+        let symbols = 20;
+
+        let start = std::time::Instant::now();
+        for _ in 0..symbols {
+            for size in sizes {
+                let mut tree = parse_svg_data(svg, &load_fonts()).unwrap();
+                render(&mut tree, size as f32).unwrap();
+            }
+        }
+        let per_size = start.elapsed();
+
+        let start = std::time::Instant::now();
+        let fontdb = load_fonts();
+        for _ in 0..symbols {
+            let mut tree = parse_svg_data(svg, &fontdb).unwrap();
+            for size in sizes {
+                render(&mut tree, size as f32).unwrap();
+            }
+        }
+        let shared = start.elapsed();
 
-    pixmap.save_png(png_path).unwrap();
+        println!("{} symbols in {} sizes: {:?} loading fonts and parsing per size, {:?} shared", symbols, sizes.len(), per_size, shared);
+        assert!(shared < per_size);
+    }
 }
@@ -0,0 +1,264 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fs,
+    path::Path,
+};
+
+use tera::{Context, ErrorKind, Tera};
+use walkdir::WalkDir;
+
+use crate::config::Status;
+use crate::error::{Error, Failure};
+use crate::utils::hash_str;
+
+/// Loads every file below `templates` into a template engine.
+///
+/// Templates which can't be parsed are left out and reported as failures, so
+/// a single broken template only fails the symbols using it.
+pub(crate) fn load_templates(templates: &Path) -> Result<(Tera, Vec<Failure>), Error> {
+    let mut failures = Vec::new();
+    let mut sources = Vec::new();
+
+    for entry in WalkDir::new(templates).into_iter().filter_map(|e| e.ok()) {
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let path = entry.path();
+        let name = template_name(templates, path);
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(error) => {
+                failures.push(Failure::new(path, Some(&name), Error::io(path, error)));
+                continue;
+            }
+        };
+
+        match Tera::default().add_raw_template(&name, &content) {
+            Err(error) if !matches!(error.kind, ErrorKind::MissingParent { .. }) => {
+                failures.push(Failure::new(path, Some(&name), error.into()));
+            }
+            _ => sources.push((name, content)),
+        }
+    }
+
+    // children of broken templates are dropped one by one until the inheritance chains resolve
+    let mut template_engine = loop {
+        let mut template_engine = Tera::default();
+        match template_engine.add_raw_templates(sources.clone()) {
+            Ok(()) => break template_engine,
+            Err(error) => match &error.kind {
+                ErrorKind::MissingParent { current, .. } => {
+                    let current = current.clone();
+                    sources.retain(|(name, _)| *name != current);
+                    failures.push(Failure::new(&templates.join(&current), Some(&current), error.into()));
+                }
+                _ => return Err(error.into()),
+            },
+        }
+    };
+    template_engine.autoescape_on(vec![".template.svg"]);
+    Ok((template_engine, failures))
+}
+
+/// A template together with everything it includes, extends or imports.
+#[derive(Debug, Clone)]
+pub(crate) struct TemplateHash {
+    /// Hash over the template and all of its dependencies
+    pub(crate) hash: String,
+    pub(crate) dependencies: Vec<String>,
+}
+
+/// Hashes every template file below `templates` together with the files it depends on, so editing
+/// a partial changes the hash of every template using it.
+pub(crate) fn template_hashes(templates: &Path) -> HashMap<String, TemplateHash> {
+    let files: BTreeMap<String, (String, Vec<String>)> = WalkDir::new(templates)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(|entry| {
+            let content = fs::read_to_string(entry.path()).ok()?;
+            Some((
+                template_name(templates, entry.path()),
+                (hash_str(&content), direct_dependencies(&content)),
+            ))
+        })
+        .collect();
+
+    files
+        .keys()
+        .map(|name| {
+            let mut seen = BTreeSet::new();
+            let mut pending = vec![name.clone()];
+            while let Some(current) = pending.pop() {
+                if seen.insert(current.clone()) {
+                    if let Some((_, dependencies)) = files.get(&current) {
+                        pending.extend(dependencies.iter().cloned());
+                    }
+                }
+            }
+
+            let hashes: Vec<String> = seen
+                .iter()
+                .map(|dependency| match files.get(dependency) {
+                    Some((hash, _)) => format!("{}:{}", dependency, hash),
+                    None => format!("{}:missing", dependency),
+                })
+                .collect();
+            seen.remove(name);
+            (name.clone(), TemplateHash {
+                hash: hash_str(&hashes.join("\n")),
+                dependencies: seen.into_iter().collect(),
+            })
+        })
+        .collect()
+}
+
+/// Templates named in the `include`, `extends` and `import` tags of a template.
+fn direct_dependencies(content: &str) -> Vec<String> {
+    let mut dependencies = Vec::new();
+    let mut rest = content;
+    while let Some(start) = rest.find("{%") {
+        rest = &rest[start + 2..];
+        let end = rest.find("%}").unwrap_or(rest.len());
+        let tag = rest[..end].trim_start_matches('-').trim_start();
+        if ["include", "extends", "import"].iter().any(|keyword| tag.starts_with(keyword)) {
+            // every quoted string of the tag, `include` also takes a list of alternatives
+            tag.split(['"', '\''])
+                .skip(1)
+                .step_by(2)
+                .for_each(|name| dependencies.push(name.to_string()));
+        }
+        rest = &rest[end..];
+    }
+    dependencies
+}
+
+/// Name of a template file as used by `{% include %}` and `{% extends %}`.
+pub(crate) fn template_name(
+    templates: &Path,
+    path: &Path,
+) -> String {
+    path.strip_prefix(templates)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .replace('\\', "/")
+}
+
+/// Template names tried for a symbol, the most specific first.
+pub(crate) fn template_candidates(
+    organisation: &str,
+    zug: &str,
+    dir: &str,
+    template: &str,
+) -> Vec<String> {
+    vec![
+        format!("{}/{}/{}.template.svg", organisation, zug, template),
+        format!("{}/{}.template.svg", zug, template),
+        format!("{}/{}.template.svg", dir, template),
+    ]
+}
+
+/// The first of the candidates known to the template engine.
+pub(crate) fn resolve_template(
+    tera: &Tera,
+    candidates: &[String],
+) -> Option<String> {
+    let names: Vec<&str> = tera.get_template_names().collect();
+    candidates
+        .iter()
+        .find(|candidate| names.contains(&candidate.as_str()))
+        .cloned()
+}
+
+/// Whether the template replaces the `groesse` block of the template it extends, like `einheit/Gruppe`,
+/// a `groesse` given by the catalogue or a request would not be drawn.
+pub(crate) fn fixes_groesse(
+    tera: &Tera,
+    template: &str,
+) -> bool {
+    let Ok(template) = tera.get_template(template) else {
+        return false;
+    };
+    let root = template.parents.last().unwrap_or(&template.name);
+    template
+        .blocks_definitions
+        .get("groesse")
+        .is_some_and(|definitions| definitions.iter().any(|(source, _)| source != root))
+}
+
+/// Whether `template` draws `status`, it has to render differently with it than without a status.
+pub(crate) fn draws_status(
+    tera: &Tera,
+    template: &str,
+    context: &Context,
+    status: &str,
+) -> Result<bool, Error> {
+    let render = |status: &str| {
+        let mut context = context.clone();
+        context.insert("status", status);
+        tera.render(template, &context)
+    };
+    let with_status = render(status)?;
+    // geplant and vermutet only dash the elements of class "frame"
+    let dashed = [Status::Geplant, Status::Vermutet]
+        .iter()
+        .any(|dashed| dashed.name() == status);
+    if dashed && !with_status.contains("class=\"frame\"") {
+        return Ok(false);
+    }
+    Ok(with_status != render("")?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dependencies_of_every_tag() {
+        let content = r#"{% extends "einheit/Einheit.template.svg" %}
+            {%- include "fonts.template.xml" -%}
+            {% import 'macros.template.xml' as macros %}
+            {% include ["a.template.xml", "b.template.xml"] ignore missing %}
+            {% block title %}Gruppe "include"{% endblock title %}
+            {{ value }}"#;
+        assert_eq!(
+            direct_dependencies(content),
+            [
+                "einheit/Einheit.template.svg",
+                "fonts.template.xml",
+                "macros.template.xml",
+                "a.template.xml",
+                "b.template.xml",
+            ],
+        );
+    }
+
+    #[test]
+    fn unterminated_tag_is_read_to_the_end() {
+        assert_eq!(direct_dependencies(r#"{% include "a.template.xml""#), ["a.template.xml"]);
+        assert!(direct_dependencies("no tags {{ value }}").is_empty());
+    }
+
+    #[test]
+    fn hash_follows_nested_dependencies() {
+        let templates = std::env::temp_dir().join(format!("taktische-zeichen-templates-{}", std::process::id()));
+        fs::create_dir_all(templates.join("einheit")).unwrap();
+        fs::write(templates.join("fonts.template.xml"), "<style />").unwrap();
+        fs::write(templates.join("einheit/Einheit.template.svg"), r#"{% include "fonts.template.xml" %}"#).unwrap();
+        fs::write(templates.join("einheit/Gruppe.template.svg"), r#"{% extends "einheit/Einheit.template.svg" %}"#).unwrap();
+        fs::write(templates.join("Broken.template.svg"), r#"{% include "missing.template.xml" %}"#).unwrap();
+
+        let before = template_hashes(&templates);
+        assert_eq!(before["einheit/Gruppe.template.svg"].dependencies, ["einheit/Einheit.template.svg", "fonts.template.xml"]);
+        assert_eq!(before["Broken.template.svg"].dependencies, ["missing.template.xml"]);
+        assert!(before["fonts.template.xml"].dependencies.is_empty());
+
+        // editing the partial changes every template using it, but nothing else
+        fs::write(templates.join("fonts.template.xml"), "<style>text {}</style>").unwrap();
+        let after = template_hashes(&templates);
+        fs::remove_dir_all(&templates).unwrap();
+        assert_ne!(before["einheit/Gruppe.template.svg"].hash, after["einheit/Gruppe.template.svg"].hash);
+        assert_ne!(before["einheit/Einheit.template.svg"].hash, after["einheit/Einheit.template.svg"].hash);
+        assert_eq!(before["Broken.template.svg"].hash, after["Broken.template.svg"].hash);
+    }
+}
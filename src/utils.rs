@@ -10,21 +10,28 @@ use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 use sha2::{Digest, Sha256};
 use walkdir::WalkDir;
 
-pub(crate) fn calc_hash(file_name: &str) -> String {
-    let mut file = File::open(file_name).unwrap();
+use crate::error::Error;
+
+pub(crate) fn calc_hash(file_name: &Path) -> Result<String, Error> {
+    let mut file = File::open(file_name).map_err(|e| Error::io(file_name, e))?;
     let mut hasher = Sha256::new();
-    io::copy(&mut file, &mut hasher).expect("TODO: panic message");
+    io::copy(&mut file, &mut hasher).map_err(|e| Error::io(file_name, e))?;
     let hash_bytes = hasher.finalize();
     let final_hash = format!("{:X}", hash_bytes);
-    final_hash
+    Ok(final_hash)
+}
+
+pub(crate) fn hash_str(content: &str) -> String {
+    format!("{:X}", Sha256::digest(content.as_bytes()))
 }
 
-pub(crate) fn save_to_file(file_name: &str, content: &str) {
-    let parent = Path::new(file_name).parent().expect("ERROR during path traversal");
-    if !parent.exists() {
-        fs::create_dir_all(parent).expect("Unable to create directory");
+pub(crate) fn save_to_file(file_name: &Path, content: &str) -> Result<(), Error> {
+    if let Some(parent) = file_name.parent() {
+        if !parent.exists() {
+            fs::create_dir_all(parent).map_err(|e| Error::io(parent, e))?;
+        }
     }
-    fs::write(file_name, content).expect("Unable to write file");
+    fs::write(file_name, content).map_err(|e| Error::io(file_name, e))
 }
 
 
@@ -34,12 +41,11 @@ pub(crate) fn create_progress_bar(
     prefix: &str,
     multi: bool,
 ) -> (ProgressBar, MultiProgress) {
-    let pb;
-    if multi {
-        pb = m.add(ProgressBar::new_spinner());
+    let pb = if multi || m.is_hidden() {
+        m.add(ProgressBar::new_spinner())
     } else {
-        pb = ProgressBar::new_spinner();
-    }
+        ProgressBar::new_spinner()
+    };
     pb.set_style(spinner_style.clone());
     pb.set_prefix(format!("[{:>7}]", prefix));
     pb.enable_steady_tick(Duration::from_millis(100));
@@ -48,20 +54,18 @@ pub(crate) fn create_progress_bar(
 
 
 pub(crate) fn path_to_title(
-    match_name: &str,
-    path: PathBuf,
+    root: &Path,
+    path: &Path,
 ) -> String {
     let mut result = String::new();
 
-    for component in path.components() {
+    for component in path.strip_prefix(root).unwrap_or(path).components() {
         if let Component::Normal(name) = component {
-            if name == match_name {
-                result.clear();
-            } else if name != "svg" {
+            if name != "svg" {
                 result.push_str(
                     name.to_str()
                         .unwrap_or("")
-                        .replace("-", " ")
+                        .replace('-', " ")
                         .as_str()
                 );
                 result.push(' ');
@@ -76,23 +80,34 @@ pub(crate) fn path_to_title(
 }
 
 pub(crate) fn path_to_id(
-    match_name: &str,
+    root: &Path,
     path: &Path,
 ) -> String {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    // status variants are grouped like the symbols they are made of, prefixed with the status
+    if let Ok(rest) = relative.strip_prefix("status") {
+        let mut components = rest.components();
+        if let Some(Component::Normal(status)) = components.next() {
+            return format!(
+                "{}-{}",
+                status.to_str().unwrap_or(""),
+                path_to_id(Path::new(""), components.as_path())
+            );
+        }
+    }
+
     let mut result = String::new();
 
-    for component in path.components() {
+    for component in relative.components() {
         if let Component::Normal(name) = component {
-            if name == match_name {
-                result.clear();
-            } else if name != "svg" {
+            if name != "svg" {
                 result.push_str(name.to_str().unwrap_or(""));
                 result.push('-');
             }
         }
     }
 
-    let mut result = result.trim_end_matches("-").to_string();
+    let mut result = result.trim_end_matches('-').to_string();
     if result.contains("original") {
         result = result.trim_start_matches("original-").to_string();
         result = format!("{}-original", result);
@@ -104,44 +119,46 @@ pub(crate) fn path_to_id(
 }
 
 pub(crate) fn process_entries<F>(
-    directory: &str,
+    directory: &Path,
     mut process_fn: F,
 ) where
     F: FnMut(PathBuf),
 {
     map_entries(directory)
-        .iter()
-        .for_each(|item| process_fn((*item.clone()).to_path_buf()))
+        .into_iter()
+        .for_each(&mut process_fn)
 }
 
 pub(crate) fn map_entries(
-    directory: &str,
+    directory: &Path,
 ) -> Vec<PathBuf> {
-    return WalkDir::new(directory)
+    WalkDir::new(directory)
         .into_iter()
         .filter_map(|e| e.ok())
-        .filter_map(|entry| {
-            return if let Some(extension) = entry.path().extension() {
-                if extension == "svg" {
-                    Some(entry)
-                } else {
-                    None
-                }
-            } else { None };
+        .filter(|entry| {
+            entry.path()
+                .extension()
+                .is_some_and(|extension| extension == "svg")
         })
         .map(|entry| entry.path().to_path_buf())
-        .collect();
+        .collect()
 }
 
 pub(crate) fn file_to_base64(
-    file_path: &str
+    file_path: &Path
 ) -> io::Result<String> {
     let mut file = File::open(file_path)?;
 
     let mut content = String::new();
     file.read_to_string(&mut content)?;
 
-    Ok(general_purpose::STANDARD.encode(&content))
+    Ok(str_to_base64(&content))
+}
+
+pub(crate) fn str_to_base64(
+    content: &str
+) -> String {
+    general_purpose::STANDARD.encode(content)
 }
 
 
@@ -151,11 +168,11 @@ pub(crate) fn join_paths(
     let mut string = paths
         .iter()
         .filter(|template| !template.is_empty())
-        .map(|x| x.as_ref())
+        .copied()
         .collect::<Vec<_>>()
         .join("/");
     string
-        .push_str("/");
+        .push('/');
     string
 }
 
@@ -165,7 +182,7 @@ pub(crate) fn join_filename(
     names
         .iter()
         .filter(|template| !template.is_empty())
-        .map(|x| x.as_ref())
+        .copied()
         .collect::<Vec<_>>()
         .join("-")
 }
@@ -179,3 +196,29 @@ pub(crate) fn uppercase_first_letter(
         Some(f) => f.to_uppercase().collect::<String>() + c.as_str(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn id_of_a_symbol_directory() {
+        let output = Path::new("build");
+        assert_eq!(path_to_id(output, Path::new("build/original/svg/THW/FK")), "THW-FK-original");
+        assert_eq!(path_to_id(output, Path::new("build/inverted/svg/FW")), "FW-inverted");
+        // the volunteer libraries keep their published names
+        assert_eq!(path_to_id(output, Path::new("build/custom/svg/original/THW/FK")), "custom-original-THW-FK-original");
+    }
+
+    #[test]
+    fn status_variants_are_prefixed_with_their_status() {
+        let output = Path::new("build");
+        assert_eq!(path_to_id(output, Path::new("build/status/geplant/original/svg/THW/FK")), "geplant-THW-FK-original");
+        assert_eq!(
+            path_to_id(output, Path::new("build/status/ausgefallen/custom/svg/inverted/THW")),
+            "ausgefallen-custom-inverted-THW-inverted",
+        );
+        // only the top level directory marks a status variant
+        assert_eq!(path_to_id(output, Path::new("build/original/svg/status/geplant")), "status-geplant-original");
+    }
+}
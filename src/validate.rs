@@ -0,0 +1,200 @@
+use std::path::Path;
+
+use tera::Tera;
+
+use crate::config::{Composition, Config, Labels, Modifiers, Source, VolunteerConfig, GROESSEN};
+use crate::error::{Error, Failure};
+use crate::plan::Plan;
+use crate::templates::{fixes_groesse, resolve_template, template_candidates};
+
+/// Checks every catalogue and volunteer entry without rendering anything.
+pub(crate) fn validate(
+    templates: &Path,
+    static_dir: &Path,
+    cfg: &Config,
+    volunteer_config: &VolunteerConfig,
+    plan: &Plan,
+    tera: &Tera,
+) -> Vec<Failure> {
+    let mut failures = Vec::new();
+
+    for (key, entries) in cfg.symbols.iter() {
+        if cfg.find_organisation(key).is_none() {
+            if let Some(entry) = entries.first() {
+                failures.push(Failure::at(
+                    &entry.source,
+                    None,
+                    Error::Invalid(format!("[[{}]] is not a declared organisation", key)),
+                ));
+            }
+        }
+    }
+
+    for (organisation, entries) in cfg.catalogue() {
+        for entry in entries.iter() {
+            if entry.compose.is_none() && !templates.join(&entry.dir).is_dir() {
+                failures.push(Failure::at(
+                    &entry.source,
+                    Some(&entry.template),
+                    Error::Invalid(format!("unknown dir {:?}", entry.dir)),
+                ));
+            }
+            match &entry.compose {
+                Some(composition) => {
+                    failures.extend(check_template(
+                        tera,
+                        &entry.source,
+                        &template_candidates(&organisation.name, &entry.zug, "compose", "symbol"),
+                    ));
+                    failures.extend(check_fachzeichen(tera, static_dir, &entry.source, &entry.template, composition));
+                }
+                None => {
+                    let candidates = template_candidates(&organisation.name, &entry.zug, &entry.dir, &entry.template);
+                    failures.extend(check_template(tera, &entry.source, &candidates));
+                    if let Some(resolved) = resolve_template(tera, &candidates) {
+                        failures.extend(check_fixed_groesse(tera, &entry.source, &entry.template, &resolved, &entry.groesse));
+                    }
+                }
+            }
+            failures.extend(check_labels(
+                &entry.source,
+                &entry.template,
+                &[("names", &entry.names), ("special", &entry.special), ("ort", &entry.ort), ("groesse", &entry.groesse)],
+            ));
+            failures.extend(check_groesse(&entry.source, &entry.template, &entry.groesse));
+            failures.extend(check_modifiers(&entry.source, &entry.template, &entry.modifiers));
+        }
+    }
+
+    if volunteer_config.enabled {
+        for person in volunteer_config.personen.iter().flatten() {
+            if cfg.find_organisation(&person.organisation).is_none() {
+                failures.push(Failure::at(
+                    &person.source,
+                    Some(&person.template),
+                    Error::Invalid(format!("{:?} is not a declared organisation", person.organisation)),
+                ));
+            }
+            failures.extend(check_template(
+                tera,
+                &person.source,
+                &template_candidates(&cfg.organisation(&person.organisation).name, &person.zug, "personen", &person.template),
+            ));
+            failures.extend(check_labels(
+                &person.source,
+                &person.template,
+                &[("volunteer", &person.volunteer), ("value", &person.value), ("ort", &person.ort)],
+            ));
+            failures.extend(check_modifiers(&person.source, &person.template, &person.modifiers));
+        }
+    }
+
+    failures.extend(plan
+        .collisions()
+        .iter()
+        .map(|collision| Failure::at(&collision.source, None, Error::Invalid(collision.message()))));
+    failures
+}
+
+fn check_template(
+    tera: &Tera,
+    source: &Source,
+    candidates: &[String],
+) -> Option<Failure> {
+    if resolve_template(tera, candidates).is_some() {
+        return None;
+    }
+    let template = candidates.last().cloned().unwrap_or_default();
+    Some(Failure::at(
+        source,
+        Some(&template),
+        Error::MissingTemplate(candidates.join(", ")),
+    ))
+}
+
+fn check_fachzeichen(
+    tera: &Tera,
+    static_dir: &Path,
+    source: &Source,
+    template: &str,
+    composition: &Composition,
+) -> Option<Failure> {
+    let fachzeichen = &composition.fachzeichen;
+    let found = if fachzeichen.is_empty() {
+        true
+    } else if fachzeichen.ends_with(".template.svg") {
+        tera.get_template_names().any(|name| name == fachzeichen)
+    } else {
+        static_dir.join(fachzeichen).is_file()
+    };
+    (!found).then(|| Failure::at(
+        source,
+        Some(template),
+        Error::Invalid(format!("unknown fachzeichen {:?}", fachzeichen)),
+    ))
+}
+
+fn check_labels(
+    source: &Source,
+    template: &str,
+    fields: &[(&str, &Labels)],
+) -> Vec<Failure> {
+    fields
+        .iter()
+        .flat_map(|(field, labels)| {
+            labels
+                .problems()
+                .into_iter()
+                .map(move |problem| Failure::at(
+                    source,
+                    Some(template),
+                    Error::Invalid(format!("{}: {}", field, problem)),
+                ))
+        })
+        .collect()
+}
+
+/// Sizes `groesse.template.xml` doesn't draw, it would throw while rendering.
+fn check_groesse(
+    source: &Source,
+    template: &str,
+    groesse: &Labels,
+) -> Vec<Failure> {
+    groesse
+        .variants()
+        .into_iter()
+        .filter(|size| !size.is_empty() && !GROESSEN.contains(&size.as_str()))
+        .map(|size| Failure::at(
+            source,
+            Some(template),
+            Error::Invalid(format!("groesse: unknown size {:?}, expected one of {}", size, GROESSEN.join(", "))),
+        ))
+        .collect()
+}
+
+fn check_fixed_groesse(
+    tera: &Tera,
+    source: &Source,
+    template: &str,
+    resolved: &str,
+    groesse: &Labels,
+) -> Option<Failure> {
+    let sized = groesse.variants().iter().any(|size| !size.is_empty());
+    (sized && fixes_groesse(tera, resolved)).then(|| Failure::at(
+        source,
+        Some(template),
+        Error::Invalid("groesse: the template draws its own size, use einheit/Einheit instead".to_string()),
+    ))
+}
+
+fn check_modifiers(
+    source: &Source,
+    template: &str,
+    modifiers: &Modifiers,
+) -> Vec<Failure> {
+    modifiers
+        .problems()
+        .into_iter()
+        .map(|problem| Failure::at(source, Some(template), Error::Invalid(problem)))
+        .collect()
+}
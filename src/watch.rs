@@ -0,0 +1,132 @@
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+    sync::mpsc,
+    time::Duration,
+};
+
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+
+use crate::Paths;
+use crate::config;
+use crate::error::Error;
+
+/// Changes arriving within this time of each other are handled by a single rebuild.
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Calls `rebuild` with the changed files whenever a template, static file or catalogue changes.
+pub(crate) fn watch<F>(
+    paths: &Paths,
+    mut rebuild: F,
+) -> Result<(), Error>
+where
+    F: FnMut(&[PathBuf]),
+{
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender)?;
+
+    let mut dirs = Vec::new();
+    for dir in [&paths.templates, &paths.static_dir] {
+        if dir.is_dir() {
+            let dir = canonical(dir);
+            watcher.watch(&dir, RecursiveMode::Recursive)?;
+            dirs.push(dir);
+        }
+    }
+    // the catalogue files are watched through their directory, so they may be created and replaced
+    let mut parents = BTreeSet::new();
+    let mut files = catalogue_files(paths);
+    watch_parents(&mut watcher, &mut parents, &files)?;
+
+    for path in dirs.iter().chain(files.iter()) {
+        println!("watching {}", path.display());
+    }
+    while let Ok(event) = receiver.recv() {
+        let mut changed = changed_paths(event);
+        while let Ok(event) = receiver.recv_timeout(DEBOUNCE) {
+            changed.extend(changed_paths(event));
+        }
+        changed.retain(|path| {
+            dirs.iter().any(|dir| path.starts_with(dir))
+                || files.contains(path)
+                || files.iter().any(|dir| dir.is_dir() && path.starts_with(dir) && path.extension().is_some_and(|e| e == "toml"))
+        });
+        changed.sort();
+        changed.dedup();
+        if !changed.is_empty() {
+            rebuild(&changed);
+
+            // the includes may have changed with the catalogue
+            let current = catalogue_files(paths);
+            for file in current.iter().filter(|file| !files.contains(file)) {
+                println!("watching {}", file.display());
+            }
+            watch_parents(&mut watcher, &mut parents, &current)?;
+            files = current;
+        }
+    }
+    Ok(())
+}
+
+/// The catalogue, every file and directory it includes and the volunteer file.
+///
+/// A catalogue which can't be read right now only contributes itself, its includes are added once it's fixed.
+fn catalogue_files(paths: &Paths) -> Vec<PathBuf> {
+    let mut files = config::parse_config(&paths.config)
+        .map(|config| config.files)
+        .unwrap_or_default();
+    files.push(canonical(&paths.config));
+    files.push(canonical(config::volunteer_path(paths.volunteer.as_deref())));
+    files.sort();
+    files.dedup();
+    files
+}
+
+/// Watches the directory of every file and included directories as a whole, each only once.
+fn watch_parents(
+    watcher: &mut impl Watcher,
+    watched: &mut BTreeSet<PathBuf>,
+    files: &[PathBuf],
+) -> Result<(), Error> {
+    for file in files {
+        let (dir, mode) = if file.is_dir() {
+            (file.as_path(), RecursiveMode::Recursive)
+        } else {
+            match file.parent() {
+                Some(parent) => (parent, RecursiveMode::NonRecursive),
+                None => continue,
+            }
+        };
+        if watched.insert(dir.to_path_buf()) {
+            watcher.watch(dir, mode)?;
+        }
+    }
+    Ok(())
+}
+
+fn changed_paths(event: notify::Result<Event>) -> Vec<PathBuf> {
+    match event {
+        Ok(event) if !matches!(event.kind, EventKind::Access(_)) => event.paths,
+        Ok(_) => Vec::new(),
+        Err(error) => {
+            eprintln!("warning: {}", error);
+            Vec::new()
+        }
+    }
+}
+
+/// Absolute path of a file which doesn't need to exist yet.
+fn canonical(path: &Path) -> PathBuf {
+    if let Ok(path) = fs::canonicalize(path) {
+        return path;
+    }
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    match (fs::canonicalize(parent), path.file_name()) {
+        (Ok(parent), Some(name)) => parent.join(name),
+        _ => path.to_path_buf(),
+    }
+}
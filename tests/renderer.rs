@@ -0,0 +1,71 @@
+use std::path::Path;
+
+use taktische_zeichen::{RasterFormat, SymbolRenderer, SymbolSpec};
+
+fn renderer() -> SymbolRenderer {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    SymbolRenderer::new(&root.join("config.toml"), &root.join("icons"), &root.join("static")).unwrap()
+}
+
+fn gruppe() -> SymbolSpec {
+    SymbolSpec {
+        organisation: "THW".to_string(),
+        template: "Gruppe".to_string(),
+        dir: "einheit".to_string(),
+        value: "B".to_string(),
+        ..SymbolSpec::default()
+    }
+}
+
+#[test]
+fn renders_a_catalogue_symbol() {
+    let svg = renderer().render_svg(&gruppe()).unwrap();
+    assert!(svg.contains("<title>THW Gruppe B</title>"));
+    assert!(svg.contains(r#""template":"Gruppe""#));
+    assert!(svg.contains(">B<"));
+}
+
+#[test]
+fn organisation_is_looked_up_by_key() {
+    let renderer = renderer();
+    assert_eq!(renderer.organisation_name("thw").as_deref(), Some("THW"));
+    let spec = SymbolSpec { organisation: "thw".to_string(), ..gruppe() };
+    assert_eq!(renderer.render_svg(&spec).unwrap(), renderer.render_svg(&gruppe()).unwrap());
+}
+
+#[test]
+fn rejects_unknown_organisations_and_templates() {
+    let renderer = renderer();
+    assert!(renderer.render_svg(&SymbolSpec { organisation: "XYZ".to_string(), ..gruppe() }).is_err());
+    assert!(renderer.render_svg(&SymbolSpec { template: "Missing".to_string(), ..gruppe() }).is_err());
+}
+
+#[test]
+fn groesse_is_only_drawn_by_templates_without_their_own() {
+    let renderer = renderer();
+    let einheit = SymbolSpec {
+        template: "Einheit".to_string(),
+        groesse: "zug".to_string(),
+        ..gruppe()
+    };
+    assert!(renderer.render_svg(&einheit).unwrap().contains("<title>THW Einheit Zug B</title>"));
+    assert!(renderer.render_svg(&SymbolSpec { groesse: "zug".to_string(), ..gruppe() }).is_err());
+}
+
+#[test]
+fn status_changes_the_drawing() {
+    let renderer = renderer();
+    let normal = renderer.render_svg(&gruppe()).unwrap();
+    let planned = renderer.render_svg(&SymbolSpec { status: "geplant".to_string(), ..gruppe() }).unwrap();
+    assert_ne!(normal, planned);
+    assert!(planned.contains("stroke-dasharray"));
+    assert!(renderer.render_svg(&SymbolSpec { status: "zerstoert".to_string(), ..gruppe() }).is_err());
+}
+
+#[test]
+fn renders_a_png_of_the_requested_size() {
+    let png = renderer().render_raster(&gruppe(), 64, RasterFormat::Png).unwrap();
+    assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+    // width and height of the IHDR chunk
+    assert_eq!(&png[16..24], [0, 0, 0, 64, 0, 0, 0, 64]);
+}